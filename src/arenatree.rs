@@ -0,0 +1,283 @@
+//! Binary search tree storing its nodes in a single arena, addressed by slot index.
+//!
+//! Inserting a value hands back a `Handle` to its node, which can be used to
+//! fetch the value again without searching the tree.
+//!
+//! A handle stays valid until its own value is removed from the tree.
+//! Inserting or removing other values never invalidates it, since removal relinks
+//! nodes rather than moving values between them. Once a value is removed its handle
+//! returns `None` for good, even if the slot is reused or the same value is inserted again.
+//!
+//! # Examples
+//!
+//! ```
+//! use algorithms::arenatree::ArenaTree;
+//!
+//! let mut t = ArenaTree::<i32>::new();
+//! let h = t.insert(5).unwrap();
+//! t.insert(3).unwrap();
+//!
+//! assert_eq!(t.get_by_handle(h), Some(&5));
+//! assert_eq!(t.remove(5), Ok(5));
+//! assert_eq!(t.get_by_handle(h), None);
+//! ```
+
+use std::cmp::Ordering;
+
+/// Opaque reference to a node in an `ArenaTree`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: usize,
+    generation: usize,
+}
+
+#[derive(Debug)]
+pub struct ArenaTree<T> where T: Ord {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    len: usize,
+}
+
+// The generation is bumped every time a slot is freed,
+// which invalidates any handles into it.
+#[derive(Debug)]
+struct Slot<T> {
+    generation: usize,
+    node: Option<ArenaNode<T>>,
+}
+
+#[derive(Debug)]
+struct ArenaNode<T> {
+    val: T,
+    left: Option<usize>,
+    right: Option<usize>,
+}
+
+// Where a node hangs: off the root, or off one side of a parent.
+#[derive(Clone, Copy)]
+enum Link {
+    Root,
+    Left(usize),
+    Right(usize),
+}
+
+impl<T> ArenaTree<T> where T: Ord {
+    pub fn new() -> ArenaTree<T> {
+        ArenaTree{ slots: Vec::new(), free: Vec::new(), root: None, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Insert an item into the tree, returning a handle to its node.
+    /// If a matching value already existed in the tree,
+    /// returns an error containing the given value.
+    pub fn insert(&mut self, val: T) -> Result<Handle, T> {
+        let mut link = Link::Root;
+        let mut current = self.root;
+
+        while let Some(i) = current {
+            let node = self.node(i);
+            match val.cmp(&node.val) {
+                Ordering::Less => {
+                    link = Link::Left(i);
+                    current = node.left;
+                },
+                Ordering::Greater => {
+                    link = Link::Right(i);
+                    current = node.right;
+                },
+                Ordering::Equal => return Err(val),
+            }
+        }
+
+        let handle = self.alloc(val);
+        self.set_link(link, Some(handle.index));
+        self.len += 1;
+        Ok(handle)
+    }
+
+    /// Get the value a handle refers to, if it's still in the tree.
+    pub fn get_by_handle(&self, handle: Handle) -> Option<&T> {
+        self.slots.get(handle.index)
+            .filter(|s| s.generation == handle.generation)
+            .and_then(|s| s.node.as_ref())
+            .map(|n| &n.val)
+    }
+
+    pub fn contains(&self, val: &T) -> bool {
+        self.find(val).is_some()
+    }
+
+    /// Remove matching node from the tree, invalidating its handle.
+    /// Returns the value if successful, errors with given value otherwise.
+    pub fn remove(&mut self, val: T) -> Result<T, T> {
+        let (link, i) = match self.find(&val) {
+            None => return Err(val),
+            Some(found) => found,
+        };
+
+        let (left, right) = {
+            let node = self.node(i);
+            (node.left, node.right)
+        };
+
+        let replacement = match (left, right) {
+            (None, None) => None,
+            (Some(c), None) | (None, Some(c)) => Some(c),
+
+            // Both subtrees present, so splice in the smallest node of
+            // the right subtree in place of the removed one.
+            (Some(l), Some(r)) => {
+                let mut parent = i;
+                let mut min = r;
+                while let Some(next) = self.node(min).left {
+                    parent = min;
+                    min = next;
+                }
+
+                if parent != i {
+                    let min_right = self.node(min).right;
+                    self.node_mut(parent).left = min_right;
+                    self.node_mut(min).right = Some(r);
+                }
+                self.node_mut(min).left = Some(l);
+                Some(min)
+            },
+        };
+
+        self.set_link(link, replacement);
+        self.len -= 1;
+        Ok(self.release(i))
+    }
+
+    // Find the node holding a value, along with where it hangs.
+    fn find(&self, val: &T) -> Option<(Link, usize)> {
+        let mut link = Link::Root;
+        let mut current = self.root;
+
+        while let Some(i) = current {
+            let node = self.node(i);
+            match val.cmp(&node.val) {
+                Ordering::Less => {
+                    link = Link::Left(i);
+                    current = node.left;
+                },
+                Ordering::Greater => {
+                    link = Link::Right(i);
+                    current = node.right;
+                },
+                Ordering::Equal => return Some((link, i)),
+            }
+        }
+
+        None
+    }
+
+    fn node(&self, i: usize) -> &ArenaNode<T> {
+        self.slots[i].node.as_ref().unwrap()
+    }
+
+    fn node_mut(&mut self, i: usize) -> &mut ArenaNode<T> {
+        self.slots[i].node.as_mut().unwrap()
+    }
+
+    fn set_link(&mut self, link: Link, child: Option<usize>) {
+        match link {
+            Link::Root => self.root = child,
+            Link::Left(p) => self.node_mut(p).left = child,
+            Link::Right(p) => self.node_mut(p).right = child,
+        }
+    }
+
+    // Store a new unlinked node, reusing a free slot if there is one.
+    fn alloc(&mut self, val: T) -> Handle {
+        let node = Some(ArenaNode{ val, left: None, right: None });
+        match self.free.pop() {
+            Some(index) => {
+                self.slots[index].node = node;
+                Handle{ index, generation: self.slots[index].generation }
+            },
+            None => {
+                self.slots.push(Slot{ generation: 0, node });
+                Handle{ index: self.slots.len() - 1, generation: 0 }
+            },
+        }
+    }
+
+    // Empty a slot, returning its value and invalidating handles to it.
+    fn release(&mut self, i: usize) -> T {
+        let slot = &mut self.slots[i];
+        slot.generation += 1;
+        self.free.push(i);
+        slot.node.take().unwrap().val
+    }
+}
+
+impl<T> Default for ArenaTree<T> where T: Ord {
+    fn default() -> ArenaTree<T> {
+        ArenaTree::new()
+    }
+}
+
+#[test]
+fn insert_get_by_handle() {
+    let mut t = ArenaTree::<i32>::new();
+    let handles: Vec<_> = [5, 3, 8, 1, 4].iter().map(|x| t.insert(*x).unwrap()).collect();
+    assert_eq!(t.insert(3), Err(3));
+    assert_eq!(t.len(), 5);
+
+    for (h, x) in handles.iter().zip(&[5, 3, 8, 1, 4]) {
+        assert_eq!(t.get_by_handle(*h), Some(x));
+    }
+}
+
+#[test]
+fn removed_handle() {
+    let mut t = ArenaTree::<i32>::new();
+    let h5 = t.insert(5).unwrap();
+    let h3 = t.insert(3).unwrap();
+    assert_eq!(t.remove(3), Ok(3));
+    assert_eq!(t.remove(3), Err(3));
+    assert_eq!(t.get_by_handle(h3), None);
+    assert_eq!(t.get_by_handle(h5), Some(&5));
+
+    // The freed slot is reused, but the old handle stays dead.
+    let h4 = t.insert(4).unwrap();
+    assert_eq!(t.get_by_handle(h3), None);
+    assert_eq!(t.get_by_handle(h4), Some(&4));
+
+    // Even when the same value comes back.
+    assert_eq!(t.remove(4), Ok(4));
+    let h4_again = t.insert(4).unwrap();
+    assert_eq!(t.get_by_handle(h4), None);
+    assert_eq!(t.get_by_handle(h4_again), Some(&4));
+}
+
+#[test]
+fn remove_keeps_other_handles() {
+    let mut t = ArenaTree::<i32>::new();
+    let values = [5, 2, 9, 1, 4, 7, 10, 3, 6, 8];
+    let handles: Vec<_> = values.iter().map(|x| t.insert(*x).unwrap()).collect();
+
+    // 5 has two children, and its successor 6 is several levels down.
+    assert_eq!(t.remove(5), Ok(5));
+    // 2 has two children, and its successor 3 is a grandchild.
+    assert_eq!(t.remove(2), Ok(2));
+    assert_eq!(t.len(), 8);
+
+    for (h, x) in handles.iter().zip(&values) {
+        if *x == 5 || *x == 2 {
+            assert_eq!(t.get_by_handle(*h), None);
+        } else {
+            assert_eq!(t.get_by_handle(*h), Some(x));
+            assert!(t.contains(x));
+        }
+    }
+}
@@ -1,309 +1,4255 @@
-use std::cmp::Ordering;
+use std::cmp::{self, Ordering};
+use std::error::Error;
+use std::fmt;
+use std::iter;
+use std::mem;
+use std::option;
+use std::str::FromStr;
+use std::vec;
 
-#[derive(Debug)]
-pub struct BinaryTree<T> where T: Ord + Copy {
+use nodealloc::{BoxAlloc, NodeAlloc};
+use num::Integer;
+use queue::Queue;
+use stack::Stack;
+
+/// An ordering for the values of a tree, used in place of their own `Ord`.
+/// Implemented for any `Fn(&T, &T) -> Ordering`, so a closure will do.
+pub trait Compare<T> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+/// Orders values by their own `Ord`, as trees do unless given a comparator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Natural;
+
+impl<T> Compare<T> for Natural where T: Ord {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+impl<T, F> Compare<T> for F where F: Fn(&T, &T) -> Ordering {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
+
+// Behaviour chosen when the tree is created. Kept once at the root,
+// and handed down to the nodes by the methods which need it.
+#[derive(Clone, Default)]
+struct Settings<C> {
+    multiset: bool,
+    lazy: bool,
+    balanced: bool,
+    // How the values are ordered.
+    order: C,
+}
+
+#[derive(Clone)]
+pub struct BinaryTree<T, C = Natural, A = BoxAlloc> {
+    root: Node<T>,
+    settings: Settings<C>,
+    // Where the boxes for the nodes below the root come from.
+    alloc: A,
+}
+
+/// A node of a `BinaryTree`, as handed to its `NodeAlloc`. Its contents are private.
+// A node is a tree of its own, with an empty node standing for an empty tree.
+#[derive(Clone, Debug)]
+pub struct Node<T> {
     val: Option<T>,
-    left: Option<Box<BinaryTree<T>>>,
-    right: Option<Box<BinaryTree<T>>>,
+    // Number of copies of the value held, only ever more than one in multiset mode.
+    count: usize,
+    // The copies beyond the first, kept in the order they were inserted.
+    copies: Vec<T>,
+    // Total copies held in the subtree rooted here, for order statistics.
+    size: usize,
+    // Number of levels in the subtree rooted here, so one more than its height.
+    levels: usize,
+    left: Option<Box<Node<T>>>,
+    right: Option<Box<Node<T>>>,
 }
 
-impl<T> BinaryTree<T> where T: Ord + Copy {
+impl<T> BinaryTree<T> where T: Ord {
     pub fn new() -> BinaryTree<T> {
-        BinaryTree{
+        BinaryTree::with_settings(Settings::default())
+    }
+
+    /// Create an empty tree in multiset mode.
+    /// Inserting a value which is already present then adds another copy of it
+    /// instead of failing, and removing it takes away one copy at a time.
+    pub fn new_multiset() -> BinaryTree<T> {
+        BinaryTree::with_settings(Settings{ multiset: true, ..Settings::default() })
+    }
+
+    /// Create an empty tree in lazy deletion mode.
+    /// Removing a value then just marks its node as a tombstone, which stays in
+    /// the structure but is skipped by iteration and lookups, until `compact`
+    /// rebuilds the tree without them. Inserting the value again revives its node.
+    /// Structural queries such as `height` still see tombstones until then.
+    pub fn new_lazy() -> BinaryTree<T> {
+        BinaryTree::with_settings(Settings{ lazy: true, ..Settings::default() })
+    }
+
+    /// Create an empty self-balancing tree, which keeps itself an AVL tree.
+    /// After each insert or remove, any node whose subtrees differ in height by
+    /// more than one is fixed up with rotations, so the height stays O(log n)
+    /// whatever order values arrive in, at the cost of a little more work per change.
+    pub fn new_balanced() -> BinaryTree<T> {
+        BinaryTree::with_settings(Settings{ balanced: true, ..Settings::default() })
+    }
+
+}
+
+impl<T> BinaryTree<T> {
+    /// Create an empty tree ordered by the given comparator rather than by the
+    /// values' own `Ord`, e.g. `|a, b| b.cmp(a)` for a tree in descending order.
+    /// Values the comparator finds equal count as duplicates.
+    /// The comparator is kept once, at the root, as part of the tree's type,
+    /// so the tree is `Send` or `Clone` just when the comparator is.
+    /// To combine a comparator with the other modes, use a `Builder`.
+    pub fn with_comparator<F>(compare: F) -> BinaryTree<T, F> where F: Fn(&T, &T) -> Ordering {
+        Builder::new().comparator(compare).build()
+    }
+
+    fn with_settings<C>(settings: Settings<C>) -> BinaryTree<T, C> {
+        BinaryTree{ root: Node::new(), settings, alloc: BoxAlloc }
+    }
+}
+
+/// Sets up an empty tree with any combination of modes, in any order,
+/// where the `new_*` constructors each give just the one, e.g.
+///
+/// ```
+/// use algorithms::binarytree::Builder;
+///
+/// let mut t = Builder::new().multiset().lazy().comparator(|a: &i32, b: &i32| b.cmp(a)).build();
+/// for x in &[1, 3, 1] {
+///     assert!(t.insert(*x).is_ok());
+/// }
+/// assert_eq!(t.iter().cloned().collect::<Vec<_>>(), vec![3, 1, 1]);
+/// ```
+#[derive(Clone, Default)]
+pub struct Builder<C = Natural> {
+    settings: Settings<C>,
+}
+
+impl Builder {
+    /// Start from a plain tree in the values' own order.
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+}
+
+impl<C> Builder<C> {
+    /// Keep copies of duplicate values, as for `BinaryTree::new_multiset`.
+    pub fn multiset(mut self) -> Builder<C> {
+        self.settings.multiset = true;
+        self
+    }
+
+    /// Leave tombstones behind on removal, as for `BinaryTree::new_lazy`.
+    pub fn lazy(mut self) -> Builder<C> {
+        self.settings.lazy = true;
+        self
+    }
+
+    /// Keep the tree an AVL tree, as for `BinaryTree::new_balanced`.
+    pub fn balanced(mut self) -> Builder<C> {
+        self.settings.balanced = true;
+        self
+    }
+
+    /// Order the values by `compare`, as for `BinaryTree::with_comparator`.
+    pub fn comparator<F>(self, compare: F) -> Builder<F> {
+        let Settings{ multiset, lazy, balanced, .. } = self.settings;
+        Builder{ settings: Settings{ multiset, lazy, balanced, order: compare } }
+    }
+
+    /// Create the empty tree.
+    pub fn build<T>(self) -> BinaryTree<T, C> where C: Compare<T> {
+        BinaryTree::with_settings(self.settings)
+    }
+}
+
+impl<T, C, A> BinaryTree<T, C, A> where C: Compare<T>, A: NodeAlloc<Node<T>> {
+    /// Number of values in the tree, counting every copy in multiset mode.
+    /// Kept up to date by `insert` and `remove`, so this is O(1).
+    pub fn len(&self) -> usize {
+        self.root.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.size == 0
+    }
+
+    /// Take the boxes for new nodes from `alloc` from now on, e.g.
+    /// `BinaryTree::new_multiset().with_alloc(::nodealloc::PoolAlloc::new())`.
+    /// The root node is held in the tree itself, so only those below it are allocated.
+    /// Nodes already in the tree are kept as they are.
+    pub fn with_alloc<B>(self, alloc: B) -> BinaryTree<T, C, B> where B: NodeAlloc<Node<T>> {
+        BinaryTree{ root: self.root, settings: self.settings, alloc }
+    }
+
+    /// The strategy the tree allocates its nodes with.
+    pub fn alloc(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Remove every value, leaving the tree as it was when created.
+    /// Settings such as multiset mode are kept.
+    pub fn clear(&mut self) {
+        self.root.release(&mut self.alloc);
+    }
+
+    /// Insert an item into the tree.
+    /// If a matching value already existed in the tree,
+    /// hands the given value back as an error.
+    pub fn insert(&mut self, val: T) -> Result<(), T> {
+        self.root.insert(val, &self.settings, &mut self.alloc, &mut false)
+    }
+
+    /// Insert an item like `insert`, then do a single rotation at the deepest node
+    /// above it whose subtrees now differ in height by more than one, if there is one.
+    /// That's much less work than fully rebalancing, while stopping runs of
+    /// ascending or descending inserts from building a long chain.
+    pub fn insert_locally_balanced(&mut self, val: T) -> Result<(), T> {
+        self.root.insert(val, &self.settings, &mut self.alloc, &mut true)
+    }
+
+    // Removes matching node from the tree.
+    // Returns the value taken out of it if successful, errors with given value otherwise.
+    // In multiset mode the copy taken is the one most recently inserted.
+    // When the node stays behind as a tombstone its value can't be moved out,
+    // so the given one is returned instead.
+    pub fn remove(&mut self, val: T) -> Result<T, T> {
+        self.root.remove(val, &self.settings, &mut self.alloc)
+    }
+
+    /// Check whether a value is in the tree, without changing it.
+    pub fn contains(&self, val: T) -> bool {
+        self.find(&val).is_some_and(|t| t.count > 0)
+    }
+
+    /// How many copies of `val` the tree holds, which is 0 or 1 outside multiset mode.
+    pub fn count(&self, val: T) -> usize {
+        self.find(&val).map_or(0, |t| t.count)
+    }
+
+    /// Number of values strictly less than `val`, whether or not it's present,
+    /// so `kth_smallest(rank(v))` finds `v` itself when it is. O(height).
+    pub fn rank(&self, val: T) -> usize {
+        self.count_less(&val)
+    }
+
+    /// Remove every value equal to `val`, returning how many there were.
+    /// In multiset mode that's all of its copies, which may be distinct items
+    /// if their ordering only looks at part of them.
+    pub fn remove_all_equal(&mut self, val: T) -> usize {
+        let copies = match self.find_mut(&val) {
+            None => return 0,
+            Some(t) => {
+                // Leave a single copy for `remove` to take out as usual,
+                // which also fixes up the sizes on the way back up.
+                let copies = t.count;
+                t.count = cmp::min(copies, 1);
+                t.copies.clear();
+                copies
+            },
+        };
+        if copies > 0 {
+            let _ = self.remove(val);
+        }
+        copies
+    }
+
+    /// Number of edges on the longest path from the root down to a leaf.
+    /// Both an empty tree and a single node have height 0.
+    /// Each node keeps the number of levels below it up to date as the tree
+    /// changes, one more than the taller of its subtrees, so this is O(1).
+    pub fn height(&self) -> usize {
+        self.root.levels.saturating_sub(1)
+    }
+
+    /// Like `height`, but gives up with an error as soon as it finds a node
+    /// deeper than `max_depth`, so a pathologically deep tree costs no more
+    /// than walking its top `max_depth` levels.
+    pub fn checked_height(&self, max_depth: usize) -> Result<usize, DepthOverflow> {
+        let mut height = 0;
+        let mut stack = Stack::new();
+        if self.root.val.is_some() {
+            stack.push((&self.root, 0));
+        }
+
+        while let Some((t, depth)) = stack.pop() {
+            if depth > max_depth {
+                return Err(DepthOverflow{ limit: max_depth });
+            }
+            height = cmp::max(height, depth);
+            for child in t.left.iter().chain(t.right.iter()) {
+                stack.push((&**child, depth + 1));
+            }
+        }
+
+        Ok(height)
+    }
+
+    /// Sum of the depths of every node, counting the root as depth 0.
+    /// Dividing by the number of nodes gives the average depth, and so the average
+    /// cost of a successful search.
+    pub fn internal_path_length(&self) -> usize {
+        let mut total = 0;
+        let mut stack = Stack::new();
+        if self.root.val.is_some() {
+            stack.push((&self.root, 0));
+        }
+
+        while let Some((t, depth)) = stack.pop() {
+            total += depth;
+            for child in t.left.iter().chain(t.right.iter()) {
+                stack.push((&**child, depth + 1));
+            }
+        }
+
+        total
+    }
+
+    /// How full the tree is compared to a perfect tree of the same height,
+    /// between 0 and 1. Dense trees are close to 1, skewed ones much lower.
+    /// An empty tree has a fill ratio of 0.
+    pub fn fill_ratio(&self) -> f64 {
+        if self.root.val.is_none() {
+            return 0.0;
+        }
+        let perfect = 2f64.powi(self.height() as i32 + 1) - 1.0;
+        self.node_count() as f64 / perfect
+    }
+
+    /// Check whether two trees have the same shape and the same values
+    /// at every node.
+    /// Walks both trees together with an explicit stack of node pairs,
+    /// so is safe to use on deep, skewed trees.
+    pub fn structurally_eq(&self, other: &BinaryTree<T, C, A>) -> bool where T: PartialEq {
+        self.root.matches_shape(&other.root, false)
+    }
+
+    /// Check whether `other` has the same values as this tree but with the
+    /// left and right children swapped at every node, as inserting the same
+    /// values in the same order under the reverse comparator would give.
+    /// A tree with no more than one node is its own mirror.
+    pub fn is_mirror_of<D, B>(&self, other: &BinaryTree<T, D, B>) -> bool
+        where T: PartialEq, D: Compare<T>, B: NodeAlloc<Node<T>> {
+        self.root.matches_shape(&other.root, true)
+    }
+
+    /// Check the binary search tree invariant holds, i.e. every value is greater than
+    /// all those in its left subtree and less than all those in its right subtree.
+    /// Tombstones are checked too, as searches still pass through them.
+    pub fn validate(&self) -> bool {
+        let mut nodes = self.iter();
+        let mut prev: Option<&T> = None;
+        while let Some(t) = nodes.next_node() {
+            let v = t.val.as_ref().unwrap();
+            if prev.is_some_and(|p| self.compare(p, v) != Ordering::Less) {
+                return false;
+            }
+            prev = Some(v);
+        }
+        true
+    }
+
+    /// Iterate over the values in the tree in ascending order.
+    /// In multiset mode each value is repeated once per copy.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(&self.root)
+    }
+
+    /// Iterate over the values in ascending order using O(1) extra space,
+    /// where `iter` needs a stack as deep as the tree.
+    /// This reverses the links along the path from the root as it goes, rather
+    /// than threading through right links as a Morris traversal would, so each
+    /// node on the way down points back up to its parent; the search order shows which side
+    /// it was entered from. Every link is put back by the time the iterator
+    /// is finished or dropped, leaving the tree exactly as it was.
+    /// As an `Iterator` it yields clones of the values, since the tree keeps them.
+    /// Values which can't be cloned can be stepped through by reference with
+    /// `InPlaceIter::next_ref` instead.
+    pub fn iter_in_place(&mut self) -> InPlaceIter<'_, T, C, A> {
+        let root = mem::take(&mut self.root);
+        let mut iter = InPlaceIter{ tree: self, focus: None, up: None, yielded: 0 };
+        if root.val.is_some() {
+            iter.focus = Some(iter.tree.alloc.alloc(root));
+            iter.descend_left();
+        } else {
+            iter.tree.root = root;
+        }
+        iter
+    }
+
+    /// Iterate in ascending order over each distinct value along with
+    /// its number of copies, which is always 1 outside multiset mode.
+    pub fn iter_groups(&self) -> impl Iterator<Item = (&T, usize)> {
+        let mut nodes = self.iter();
+        iter::from_fn(move || {
+            nodes.find_node(|n| n.count > 0).map(|n| (n.val.as_ref().unwrap(), n.count))
+        })
+    }
+
+    /// Iterate over the values held in leaf nodes, from left to right.
+    /// In lazy deletion mode, a node counts as a leaf once everything
+    /// below it has been removed, and removed values are never leaves.
+    pub fn leaves(&self) -> impl Iterator<Item = &T> {
+        let mut nodes = self.iter();
+        iter::from_fn(move || {
+            nodes.find_node(|n| n.count > 0 && n.live_children().next().is_none())
+                .and_then(|n| n.val.as_ref())
+        })
+    }
+
+    /// Iterate over the `(left, right)` child values of every node with two children.
+    /// Nodes are visited in pre-order.
+    pub fn sibling_pairs(&self) -> impl Iterator<Item = (&T, &T)> {
+        let mut stack = Stack::new();
+        if self.root.val.is_some() {
+            stack.push(&self.root);
+        }
+
+        iter::from_fn(move || {
+            while let Some(t) = stack.pop() {
+                if let Some(ref r) = t.right {
+                    stack.push(&**r);
+                }
+                if let Some(ref l) = t.left {
+                    stack.push(&**l);
+                }
+                if let (Some(l), Some(r)) = (&t.left, &t.right) {
+                    return Some((l.val.as_ref().unwrap(), r.val.as_ref().unwrap()));
+                }
+            }
+            None
+        })
+    }
+
+    /// Iterate over every `(parent, child)` pair of values, each link once.
+    /// Links are given in pre-order of their child, left before right.
+    /// Nodes removed in lazy deletion mode are passed over, linking the
+    /// values below them to the nearest value still above.
+    pub fn edges(&self) -> impl Iterator<Item = (&T, &T)> {
+        // Each node is pushed along with the nearest value above it, if any.
+        fn push_children<'a, T>(stack: &mut Stack<(Option<&'a T>, &'a Node<T>)>,
+                                above: Option<&'a T>, t: &'a Node<T>) {
+            let above = if t.count > 0 { t.val.as_ref() } else { above };
+            for child in t.live_children().rev() {
+                stack.push((above, child));
+            }
+        }
+
+        let mut stack = Stack::new();
+        push_children(&mut stack, None, &self.root);
+
+        iter::from_fn(move || {
+            while let Some((above, child)) = stack.pop() {
+                push_children(&mut stack, above, child);
+                if let (Some(parent), true) = (above, child.count > 0) {
+                    return Some((parent, child.val.as_ref().unwrap()));
+                }
+            }
+            None
+        })
+    }
+
+    /// Iterate over the values in pre-order, each node before its children
+    /// and the left subtree before the right.
+    /// Like `iter`, repeats each value once per copy and skips tombstones.
+    pub fn iter_preorder(&self) -> impl Iterator<Item = &T> {
+        let mut stack = Stack::new();
+        if self.root.val.is_some() {
+            stack.push(&self.root);
+        }
+
+        let nodes = iter::from_fn(move || {
+            let t = stack.pop()?;
+            for child in t.right.iter().chain(t.left.iter()) {
+                stack.push(&**child);
+            }
+            Some(t)
+        });
+        nodes.flat_map(|t| t.values_here())
+    }
+
+    /// Iterate over the values in post-order, each node after both of its children.
+    pub fn iter_postorder(&self) -> impl Iterator<Item = &T> {
+        // Nodes are pushed twice: first to have their children pushed above
+        // them, then again marked as done to be yielded once those are popped.
+        let mut stack = Stack::new();
+        if self.root.val.is_some() {
+            stack.push((&self.root, false));
+        }
+
+        let nodes = iter::from_fn(move || {
+            loop {
+                let (t, done) = stack.pop()?;
+                if done {
+                    return Some(t);
+                }
+                stack.push((t, true));
+                for child in t.right.iter().chain(t.left.iter()) {
+                    stack.push((&**child, false));
+                }
+            }
+        });
+        nodes.flat_map(|t| t.values_here())
+    }
+
+    /// Iterate over the values breadth-first, level by level from the root down,
+    /// each level from left to right.
+    pub fn iter_levelorder(&self) -> impl Iterator<Item = &T> {
+        let mut queue = Queue::new();
+        if self.root.val.is_some() {
+            queue.enqueue(&self.root);
+        }
+
+        let nodes = iter::from_fn(move || {
+            let t = queue.dequeue()?;
+            for child in t.left.iter().chain(t.right.iter()) {
+                queue.enqueue(&**child);
+            }
+            Some(t)
+        });
+        nodes.flat_map(|t| t.values_here())
+    }
+
+    /// Iterate in ascending order over the values `v` with `low <= v <= high`,
+    /// without visiting subtrees which lie wholly outside those bounds.
+    /// Yields nothing if `low > high`.
+    pub fn range(&self, low: T, high: T) -> impl Iterator<Item = &T> {
+        let above = move |v: &T| self.compare(v, &low) != Ordering::Less;
+        let below = move |v: &T| self.compare(v, &high) != Ordering::Greater;
+        self.range_nodes(above, below).flat_map(|t| t.values_here())
+    }
+
+    /// Check membership of many values at once, giving a result per query.
+    /// The queries must be in ascending order: the tree is walked in order
+    /// alongside them, in O(n + q) rather than a descent per query.
+    pub fn contains_batch_sorted(&self, sorted_queries: &[T]) -> Vec<bool> {
+        let mut values = self.iter().peekable();
+        sorted_queries.iter().map(|q| {
+            while values.next_if(|v| self.compare(v, q) == Ordering::Less).is_some() {}
+            values.peek().is_some_and(|v| self.compare(v, q) == Ordering::Equal)
+        }).collect()
+    }
+
+    /// Count how many values are present in both trees.
+    /// Walks the two trees in order in lockstep, so takes O(n + m)
+    /// time and never builds a result tree.
+    pub fn intersection_count(&self, other: &BinaryTree<T, C, A>) -> usize {
+        let mut ours = self.iter();
+        let mut theirs = other.iter();
+        let mut a = ours.next();
+        let mut b = theirs.next();
+        let mut count = 0;
+
+        while let (Some(x), Some(y)) = (a, b) {
+            match self.compare(x, y) {
+                Ordering::Less => a = ours.next(),
+                Ordering::Greater => b = theirs.next(),
+                Ordering::Equal => {
+                    count += 1;
+                    a = ours.next();
+                    b = theirs.next();
+                },
+            }
+        }
+
+        count
+    }
+
+    /// Walk the tree in order alongside another ascending source, yielding every
+    /// item from either in one sorted sequence, tagged with where it came from.
+    /// Equal items from each side are paired up as `MergeSide::Both`.
+    pub fn merge_with<'a, I>(&'a self, other: I) -> impl Iterator<Item = MergeSide<&'a T>>
+        where I: IntoIterator<Item = &'a T>
+    {
+        let mut ours = self.iter().peekable();
+        let mut theirs = other.into_iter().peekable();
+        iter::from_fn(move || {
+            let side = match (ours.peek(), theirs.peek()) {
+                (Some(x), Some(y)) => self.compare(x, y),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => return None,
+            };
+            Some(match side {
+                Ordering::Less => MergeSide::Tree(ours.next().unwrap()),
+                Ordering::Greater => MergeSide::Other(theirs.next().unwrap()),
+                Ordering::Equal => MergeSide::Both(ours.next().unwrap(), theirs.next().unwrap()),
+            })
+        })
+    }
+
+    // Compare two values in the order the tree is kept in.
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self.settings.order.compare(a, b)
+    }
+
+    // Step in order through the nodes whose values satisfy both bounds, where `above`
+    // holds for every value past the low end and `below` for every value before the high end.
+    // Only visits subtrees that can contain such values.
+    fn range_nodes<L, H>(&self, above: L, below: H) -> impl Iterator<Item = &Node<T>>
+        where L: Fn(&T) -> bool, H: Fn(&T) -> bool
+    {
+        let mut stack = Stack::new();
+        let mut node = if self.root.val.is_some() { Some(&self.root) } else { None };
+
+        iter::from_fn(move || {
+            // Walk down the left edge, skipping anything below the low end.
+            while let Some(t) = node {
+                if above(t.val.as_ref().unwrap()) {
+                    stack.push(t);
+                    node = t.left.as_deref();
+                } else {
+                    node = t.right.as_deref();
+                }
+            }
+
+            // Everything left on the stack is larger still once past the high end.
+            let t = stack.pop()?;
+            if !below(t.val.as_ref().unwrap()) {
+                return None;
+            }
+            node = t.right.as_deref();
+            Some(t)
+        })
+    }
+
+    // Borrowing form of `first_invariant_violation`.
+    fn first_unordered_pair(&self) -> Option<(&T, &T)> {
+        let mut values = self.iter_groups().map(|(v, _)| v);
+        let mut prev = values.next()?;
+        for v in values {
+            if self.compare(prev, v) != Ordering::Less {
+                return Some((prev, v));
+            }
+            prev = v;
+        }
+        None
+    }
+
+    // Number of values strictly greater than `val`, in O(height).
+    fn count_greater(&self, val: &T) -> usize {
+        let mut greater = 0;
+        let mut node = if self.root.val.is_some() { Some(&self.root) } else { None };
+
+        while let Some(t) = node {
+            match self.compare(val, t.val.as_ref().unwrap()) {
+                Ordering::Less => {
+                    greater += t.count + Node::size_of(&t.right);
+                    node = t.left.as_deref();
+                },
+                Ordering::Greater => node = t.right.as_deref(),
+                Ordering::Equal => return greater + Node::size_of(&t.right),
+            }
+        }
+
+        greater
+    }
+
+    // The node holding `val`, including a tombstone.
+    fn find(&self, val: &T) -> Option<&Node<T>> {
+        self.root.val.as_ref()?;
+        let mut t = &self.root;
+        loop {
+            t = match self.compare(val, t.val.as_ref().unwrap()) {
+                Ordering::Less => t.left.as_deref()?,
+                Ordering::Greater => t.right.as_deref()?,
+                Ordering::Equal => return Some(t),
+            };
+        }
+    }
+
+    fn find_mut(&mut self, val: &T) -> Option<&mut Node<T>> {
+        self.root.val.as_ref()?;
+        let order = &self.settings.order;
+        let mut t = &mut self.root;
+        loop {
+            let next = match order.compare(val, t.val.as_ref().unwrap()) {
+                Ordering::Less => t.left.as_deref_mut(),
+                Ordering::Greater => t.right.as_deref_mut(),
+                Ordering::Equal => return Some(t),
+            };
+            t = next?;
+        }
+    }
+
+    // Number of values strictly less than `val`, in O(height).
+    fn count_less(&self, val: &T) -> usize {
+        let mut less = 0;
+        let mut node = if self.root.val.is_some() { Some(&self.root) } else { None };
+
+        while let Some(t) = node {
+            match self.compare(val, t.val.as_ref().unwrap()) {
+                Ordering::Less => node = t.left.as_deref(),
+                Ordering::Greater => {
+                    less += t.count + Node::size_of(&t.left);
+                    node = t.right.as_deref();
+                },
+                Ordering::Equal => return less + Node::size_of(&t.left),
+            }
+        }
+
+        less
+    }
+
+    // The value at position `k` in ascending order, counting from 0, in O(height).
+    fn select(&self, mut k: usize) -> Option<&T> {
+        if k >= self.root.size {
+            return None;
+        }
+
+        let mut t = &self.root;
+        loop {
+            let left = Node::size_of(&t.left);
+            if k < left {
+                t = t.left.as_deref().unwrap();
+            } else if k < left + t.count {
+                return Some(t.value_at(k - left));
+            } else {
+                k -= left + t.count;
+                t = t.right.as_deref().unwrap();
+            }
+        }
+    }
+
+    // Number of nodes, including any tombstones.
+    fn node_count(&self) -> usize {
+        let mut nodes = self.iter();
+        iter::from_fn(|| nodes.next_node()).count()
+    }
+}
+
+impl<T> Node<T> {
+    fn new() -> Node<T> {
+        Node{
             val: None,
+            count: 0,
+            copies: Vec::new(),
+            size: 0,
+            levels: 0,
             left: None,
             right: None,
         }
     }
 
-    /// Insert an item into the tree.
-    /// If a matching value already existed in the tree,
-    /// returns an error containing the existing value.
-    pub fn insert(&mut self, val: T) -> Result<T, T> {
-        // Deal with the empty case first. 
+    // Insert, doing a rotation at the first unbalanced node on the way back up
+    // while `rotate` is set, and clearing it once done.
+    fn insert<C, A>(&mut self, val: T, settings: &Settings<C>, alloc: &mut A, rotate: &mut bool) -> Result<(), T>
+        where C: Compare<T>, A: NodeAlloc<Node<T>>
+    {
+        // Deal with the empty case first.
         if self.val.is_none() {
             self.val = Some(val);
-            return Ok(val);
+            self.count = 1;
+            self.update();
+            return Ok(());
         }
 
         // Get the right subtree to put the value in.
-        let mut subtree = match val.cmp(self.val.as_ref().unwrap()) {
+        let subtree = match settings.order.compare(&val, self.val.as_ref().unwrap()) {
             Ordering::Less => &mut self.left,
             Ordering::Greater => &mut self.right,
-            Ordering::Equal => return Err(self.val.unwrap()),
+            // Tombstones come back to life, holding the new value.
+            Ordering::Equal if self.count == 0 => {
+                self.val = Some(val);
+                self.count = 1;
+                self.size += 1;
+                return Ok(());
+            },
+            // Multisets take another copy.
+            Ordering::Equal if settings.multiset => {
+                self.copies.push(val);
+                self.count += 1;
+                self.size += 1;
+                return Ok(());
+            },
+            Ordering::Equal => return Err(val),
         };
 
         match *subtree {
             None => {
                 // No subtree, create one containing the value.
-                let mut t = BinaryTree::<T>::new();
+                let mut t = Node::new();
                 t.val = Some(val);
-                *subtree = Some(Box::new(t));
+                t.count = 1;
+                t.update();
+                *subtree = Some(alloc.alloc(t));
             },
             Some(ref mut t) => {
                 // Recursively insert into subtree.
-                try!(t.insert(val));
+                t.insert(val, settings, alloc, rotate)?;
             },
         };
 
-        Ok(val)
+        self.update();
+        if settings.balanced {
+            self.rebalance();
+        } else if *rotate {
+            match self.balance_factor() {
+                b if b > 1 => self.rotate_right(),
+                b if b < -1 => self.rotate_left(),
+                _ => return Ok(()),
+            }
+            *rotate = false;
+        }
+        Ok(())
     }
 
-    // Removes matching node from the tree.
-    // Returns it if successful, errors with given value otherwise.
-    pub fn remove(&mut self, val: T) -> Result<T, T> {
+    fn remove<C, A>(&mut self, val: T, settings: &Settings<C>, alloc: &mut A) -> Result<T, T>
+        where C: Compare<T>, A: NodeAlloc<Node<T>>
+    {
         // Deal with the empty case first.
         if self.val.is_none() {
             return Err(val);
         }
 
-        let result = match val.cmp(self.val.as_ref().unwrap()) {
+        let result = match settings.order.compare(&val, self.val.as_ref().unwrap()) {
             Ordering::Less => {
                 match self.left {
                     None => Err(val),
-                    Some(ref mut t) => t.remove(val),
+                    Some(ref mut t) => t.remove(val, settings, alloc),
                 }
             }
 
             Ordering::Greater => {
                 match self.right {
                     None => Err(val),
-                    Some(ref mut t) => t.remove(val),
+                    Some(ref mut t) => t.remove(val, settings, alloc),
                 }
             }
 
+            // Already a tombstone.
+            Ordering::Equal if self.count == 0 => Err(val),
+
+            // Other copies remain, or deletion is lazy, so the node stays.
+            Ordering::Equal if self.count > 1 || settings.lazy => {
+                self.count -= 1;
+                Ok(self.copies.pop().unwrap_or(val))
+            }
+
             Ordering::Equal => {
                 let result = Ok(self.val.take().unwrap());
+                self.count = 0;
 
-                let (new_val, new_left, new_right) = match self {
+                match (self.left.take(), self.right.take()) {
                     // No subtrees.
                     // Tree is empty.
-                    &mut BinaryTree{left: None, right: None, ..} => {
-                        (None, None, None)
-                    },
+                    (None, None) => {},
 
                     // Only one subtree.
                     // Just replace this node with it.
-                    &mut BinaryTree{left: None, right: Some(ref mut t), ..} |
-                    &mut BinaryTree{left: Some(ref mut t), right: None, ..} => {
-                        (t.val.take(), t.left.take(), t.right.take())
-                    },
+                    (Some(t), None) | (None, Some(t)) => *self = alloc.free(t),
 
                     // Have both subtrees.
-                    // This is the difficult case.
-                    &mut BinaryTree{
-                        left: Some(ref mut l),
-                        right: Some(_),
-                        ..
-                    } => {
-                        let v = l.collapse_rightmost();
-                        let copy = BinaryTree{val: l.val.take(), left: l.left.take(), right: l.right.take()};
-                        (v, Some(Box::new(copy)), self.right.take())
+                    // Take over the value of the rightmost node on the left.
+                    (Some(l), Some(r)) => {
+                        let (mut max, rest) = Node::take_rightmost(l, settings.balanced);
+                        self.val = max.val.take();
+                        self.count = max.count;
+                        self.copies = mem::take(&mut max.copies);
+                        self.left = rest;
+                        self.right = Some(r);
+                        alloc.free(max);
                     },
-                };
-
-                self.val = new_val;
-                self.left = new_left;
-                self.right = new_right;
+                }
 
                 result
             }
         };
 
-        self.prune();
+        self.prune(alloc);
+        self.update();
+        if settings.balanced {
+            self.rebalance();
+        }
 
         result
     }
 
-    // Remove the rightmost value in tree and return its value.
-    fn collapse_rightmost(&mut self) -> Option<T> {
-        let val = match self.right {
-            None => self.val.take(),
-            Some(ref mut t) => t.collapse_rightmost(),
-        };
+    // Walk both trees together comparing each pair of nodes, pairing each node's
+    // children with the same side in the other tree, or the opposite side if mirrored.
+    fn matches_shape(&self, other: &Node<T>, mirrored: bool) -> bool where T: PartialEq {
+        let mut pairs = Stack::new();
+        pairs.push((self, other));
 
-        self.prune();
+        while let Some((a, b)) = pairs.pop() {
+            if a.val != b.val || a.count != b.count || a.copies != b.copies {
+                return false;
+            }
+
+            let (b_left, b_right) = if mirrored { (&b.right, &b.left) } else { (&b.left, &b.right) };
+            for &(l, r) in &[(&a.left, b_left), (&a.right, b_right)] {
+                match (l, r) {
+                    (None, None) => {},
+                    (Some(l), Some(r)) => pairs.push((&**l, &**r)),
+                    _ => return false,
+                }
+            }
+        }
 
-        val
+        true
     }
 
-    // Deletes any empty children.
-    fn prune(&mut self) {
-        let del_left = match self.left {
-            None => false,
-            Some(ref t) => t.val.is_none(),
-        };
+    // Every copy of the value held here, none for a tombstone.
+    // The child in `subtree`, unless it holds no values: in lazy deletion mode,
+    // a subtree whose values have all been removed is left out of its shape.
+    fn live(subtree: &Option<Box<Node<T>>>) -> Option<&Node<T>> {
+        subtree.as_deref().filter(|t| t.size > 0)
+    }
 
-        let del_right = match self.right {
-            None => false,
-            Some(ref t) => t.val.is_none(),
-        };
+    // The children which still hold values, left then right.
+    fn live_children(&self) -> impl DoubleEndedIterator<Item = &Node<T>> {
+        Node::live(&self.left).into_iter().chain(Node::live(&self.right))
+    }
 
-        if del_left {
-            self.left = None;
+    fn values_here(&self) -> impl Iterator<Item = &T> {
+        let live = if self.count > 0 { self.val.as_ref() } else { None };
+        live.into_iter().chain(self.copies.iter())
+    }
+
+    // The `i`-th copy of the value held here, counting from 0.
+    fn value_at(&self, i: usize) -> &T {
+        if i == 0 { self.val.as_ref().unwrap() } else { &self.copies[i - 1] }
+    }
+
+    // Build a height-balanced tree from ascending distinct values
+    // paired with any further copies of them.
+    fn balanced<A>(mut groups: Vec<(T, Vec<T>)>, alloc: &mut A) -> Node<T> where A: NodeAlloc<Node<T>> {
+        let mut t = Node::new();
+        if !groups.is_empty() {
+            let right = groups.split_off(groups.len() / 2 + 1);
+            let (val, copies) = groups.pop().unwrap();
+            t.val = Some(val);
+            t.count = 1 + copies.len();
+            t.copies = copies;
+            t.left = Node::balanced_subtree(groups, alloc);
+            t.right = Node::balanced_subtree(right, alloc);
+            t.update();
         }
-        if del_right {
-            self.right = None;
+        t
+    }
+
+    fn balanced_subtree<A>(groups: Vec<(T, Vec<T>)>, alloc: &mut A) -> Option<Box<Node<T>>>
+        where A: NodeAlloc<Node<T>>
+    {
+        if groups.is_empty() {
+            None
+        } else {
+            let t = Node::balanced(groups, alloc);
+            Some(alloc.alloc(t))
         }
     }
-}
 
-#[test]
-fn insert() {
-    let mut t = BinaryTree::<i32>::new();
-    assert!(t.insert(3).is_ok());
-    assert!(t.insert(5).is_ok());
-    assert_eq!(t.val.unwrap(), 3);
-    assert_eq!(t.right.as_ref().unwrap().val.unwrap(), 5);
-}
+    // Hand every node below this one back to `alloc`, leaving this one empty.
+    fn release<A>(&mut self, alloc: &mut A) where A: NodeAlloc<Node<T>> {
+        let mut stack = Stack::new();
+        self.push_children(&mut stack);
 
-#[test]
-fn duplicate() {
-    let mut t = BinaryTree::<i32>::new();
-    assert!(t.insert(3).is_ok());
-    assert!(t.insert(3).is_err());
-    assert!(t.insert(3).is_err());
-    assert!(t.insert(5).is_ok());
-    assert!(t.insert(5).is_err());
-    assert!(t.insert(3).is_err());
+        while let Some(mut t) = stack.pop() {
+            t.push_children(&mut stack);
+            alloc.free(t);
+        }
+        *self = Node::new();
+    }
+
+    // Move this node's children, those it has, onto the stack.
+    fn push_children(&mut self, stack: &mut Stack<Box<Node<T>>>) {
+        if let Some(l) = self.left.take() {
+            stack.push(l);
+        }
+        if let Some(r) = self.right.take() {
+            stack.push(r);
+        }
+    }
+
+    fn size_of(subtree: &Option<Box<Node<T>>>) -> usize {
+        subtree.as_ref().map_or(0, |t| t.size)
+    }
+
+    fn levels_of(subtree: &Option<Box<Node<T>>>) -> usize {
+        subtree.as_ref().map_or(0, |t| t.levels)
+    }
+
+    // Recalculate the size and levels of this subtree from its children.
+    fn update(&mut self) {
+        self.size = self.count + Node::size_of(&self.left) + Node::size_of(&self.right);
+        self.levels = if self.val.is_none() {
+            0
+        } else {
+            1 + cmp::max(Node::levels_of(&self.left), Node::levels_of(&self.right))
+        };
+    }
+
+    // How much taller the left subtree is than the right.
+    fn balance_factor(&self) -> isize {
+        Node::levels_of(&self.left) as isize - Node::levels_of(&self.right) as isize
+    }
+
+    // Restore the AVL property at this node, given its subtrees already have it,
+    // with a single or double rotation.
+    fn rebalance(&mut self) {
+        match self.balance_factor() {
+            2 => {
+                let left = self.left.as_mut().unwrap();
+                if left.balance_factor() < 0 {
+                    left.rotate_left();
+                }
+                self.rotate_right();
+            },
+            -2 => {
+                let right = self.right.as_mut().unwrap();
+                if right.balance_factor() > 0 {
+                    right.rotate_right();
+                }
+                self.rotate_left();
+            },
+            _ => {},
+        }
+    }
+
+    // Lift the left child into this node's place, making this node its right child.
+    // A node is a tree of its own here, so the two swap contents.
+    fn rotate_right(&mut self) {
+        let mut lifted = self.left.take().unwrap();
+        self.left = lifted.right.take();
+        mem::swap(self, &mut *lifted);
+        lifted.update();
+        self.right = Some(lifted);
+        self.update();
+    }
+
+    // Lift the right child into this node's place, making this node its left child.
+    fn rotate_left(&mut self) {
+        let mut lifted = self.right.take().unwrap();
+        self.right = lifted.left.take();
+        mem::swap(self, &mut *lifted);
+        lifted.update();
+        self.left = Some(lifted);
+        self.update();
+    }
+
+    // Detach the rightmost node of a subtree, rebalancing on the way back up if asked.
+    // Returns it along with what remains of the subtree.
+    fn take_rightmost(mut t: Box<Node<T>>, balanced: bool) -> (Box<Node<T>>, Option<Box<Node<T>>>) {
+        match t.right.take() {
+            None => {
+                let rest = t.left.take();
+                (t, rest)
+            },
+            Some(r) => {
+                let (max, rest) = Node::take_rightmost(r, balanced);
+                t.right = rest;
+                t.update();
+                if balanced {
+                    t.rebalance();
+                }
+                (max, Some(t))
+            },
+        }
+    }
+
+    // Deletes any empty children.
+    fn prune<A>(&mut self, alloc: &mut A) where A: NodeAlloc<Node<T>> {
+        let del_left = match self.left {
+            None => false,
+            Some(ref t) => t.val.is_none(),
+        };
+
+        let del_right = match self.right {
+            None => false,
+            Some(ref t) => t.val.is_none(),
+        };
+
+        if del_left {
+            alloc.free(self.left.take().unwrap());
+        }
+        if del_right {
+            alloc.free(self.right.take().unwrap());
+        }
+    }
+}
+
+impl<T> BinaryTree<T> where T: Ord + Clone {
+    /// Build a height-balanced tree from strictly ascending values,
+    /// by making the middle value the root and recursing either side.
+    /// Much better than inserting sorted values one at a time, which builds a chain.
+    pub fn from_sorted_slice(data: &[T]) -> BinaryTree<T> {
+        debug_assert!(data.windows(2).all(|w| w[0] < w[1]),
+                      "from_sorted_slice needs strictly ascending values");
+        let groups = data.iter().map(|v| (v.clone(), Vec::new())).collect();
+        let mut t = BinaryTree::new();
+        t.root = Node::balanced(groups, &mut t.alloc);
+        t
+    }
+
+    /// Like `from_sorted_slice`, but first checks the values really are strictly
+    /// ascending, failing with the index of the first value which isn't.
+    pub fn try_from_sorted_slice(data: &[T]) -> Result<BinaryTree<T>, SortError> {
+        for (i, w) in data.windows(2).enumerate() {
+            match w[0].cmp(&w[1]) {
+                Ordering::Less => {},
+                Ordering::Equal => return Err(SortError::Duplicate(i + 1)),
+                Ordering::Greater => return Err(SortError::OutOfOrder(i + 1)),
+            }
+        }
+        Ok(BinaryTree::from_sorted_slice(data))
+    }
+}
+
+impl<T, C, A> BinaryTree<T, C, A> where T: Clone, C: Compare<T>, A: NodeAlloc<Node<T>> {
+    /// Copy the values out into a `Vec` in ascending order,
+    /// with each value repeated once per copy in multiset mode.
+    pub fn to_sorted_vec(&self) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.len());
+        values.extend(self.iter().cloned());
+        values
+    }
+
+    /// The values of the nodes at depth `d`, with the root at depth 0,
+    /// from left to right. Empty for depths below the bottom of the tree.
+    /// Nodes removed in lazy deletion mode keep their place, but aren't listed.
+    pub fn values_at_depth(&self, d: usize) -> Vec<T> {
+        let mut values = Vec::new();
+        let mut stack = Stack::new();
+        if self.root.val.is_some() {
+            stack.push((&self.root, 0));
+        }
+
+        // Pre-order visits each level from left to right.
+        while let Some((t, depth)) = stack.pop() {
+            if depth == d {
+                if t.count > 0 {
+                    values.push(t.val.clone().unwrap());
+                }
+                continue;
+            }
+            for child in t.live_children().rev() {
+                stack.push((child, depth + 1));
+            }
+        }
+
+        values
+    }
+
+    /// The values at either end of a longest path between two nodes, smaller first.
+    /// Where several paths are equally long, the one whose top node comes first
+    /// in post-order wins, and each end is the leftmost of the deepest candidates.
+    /// A single node is a path of length 0, with itself at both ends.
+    pub fn diameter_endpoints(&self) -> Option<(T, T)> {
+        self.root.val.as_ref()?;
+
+        let mut best = None;
+        self.root.diameter_walk(&mut best);
+        best.map(|(_, a, b)| (a.clone(), b.clone()))
+    }
+
+    /// Apply `f` to every value, then rebuild the tree so it's ordered correctly
+    /// however `f` moved values around. Values which `f` maps onto the same result
+    /// collide, and only one of each is kept; returns how many were dropped.
+    /// In multiset mode colliding values are merged instead, so none are dropped.
+    /// The rebuilt tree is height-balanced.
+    pub fn remap_keys<F>(&mut self, f: F) -> usize where F: Fn(T) -> T {
+        let mut values: Vec<T> = self.iter().map(|v| f(v.clone())).collect();
+        values.sort_by(|a, b| self.compare(a, b));
+
+        let mut groups: Vec<(T, Vec<T>)> = Vec::with_capacity(values.len());
+        let mut dropped = 0;
+        for v in values {
+            match groups.last_mut() {
+                Some(last) if self.compare(&last.0, &v) == Ordering::Equal => {
+                    if self.settings.multiset {
+                        last.1.push(v);
+                    } else {
+                        dropped += 1;
+                    }
+                },
+                _ => groups.push((v, Vec::new())),
+            }
+        }
+
+        self.root.release(&mut self.alloc);
+        self.root = Node::balanced(groups, &mut self.alloc);
+        dropped
+    }
+
+    /// Rebuild just the subtree rooted at the node holding `root_val` so it's
+    /// height-balanced, leaving the rest of the tree as it is.
+    /// Returns whether that node was found.
+    pub fn rebalance_subtree(&mut self, root_val: T) -> bool {
+        self.root.rebalance_subtree(root_val, &self.settings.order, &mut self.alloc)
+    }
+
+    /// Rebuild the tree height-balanced, physically dropping any tombstones
+    /// left behind by removals in lazy deletion mode.
+    pub fn compact(&mut self) {
+        let groups = self.root.groups();
+        self.root.release(&mut self.alloc);
+        self.root = Node::balanced(groups, &mut self.alloc);
+    }
+
+    /// Collect, in ascending order, the values strictly between `lo` and `hi`.
+    pub fn between(&self, lo: T, hi: T) -> Vec<T> {
+        self.collect_range(|v| self.compare(v, &lo) == Ordering::Greater,
+                           |v| self.compare(v, &hi) == Ordering::Less)
+    }
+
+    /// Collect, in ascending order, the values `v` with `lo <= v <= hi`.
+    pub fn between_inclusive(&self, lo: T, hi: T) -> Vec<T> {
+        self.collect_range(|v| self.compare(v, &lo) != Ordering::Less,
+                           |v| self.compare(v, &hi) != Ordering::Greater)
+    }
+
+    /// Find the first pair of values that are out of order, if any.
+    /// Walks the tree in order and returns the first adjacent pair `(a, b)` with
+    /// `a >= b`, which is handy for tracking down a tree corrupted by manual edits.
+    pub fn first_invariant_violation(&self) -> Option<(T, T)> {
+        self.first_unordered_pair().map(|(a, b)| (a.clone(), b.clone()))
+    }
+
+    /// The largest value in the tree which is less than or equal to `val`.
+    /// Found by counting the values up to `val` and selecting the last of them,
+    /// so tombstones are passed over.
+    pub fn floor(&self, val: T) -> Option<T> {
+        match self.len() - self.count_greater(&val) {
+            0 => None,
+            up_to => self.select(up_to - 1).cloned(),
+        }
+    }
+
+    /// The smallest value in the tree which is greater than or equal to `val`.
+    pub fn ceil(&self, val: T) -> Option<T> {
+        self.select(self.count_less(&val)).cloned()
+    }
+
+    /// The smallest value in the tree strictly greater than `val`,
+    /// whether or not `val` itself is present.
+    pub fn successor(&self, val: T) -> Option<T> {
+        self.select(self.len() - self.count_greater(&val)).cloned()
+    }
+
+    /// The largest value in the tree strictly less than `val`.
+    pub fn predecessor(&self, val: T) -> Option<T> {
+        self.select(self.count_less(&val).checked_sub(1)?).cloned()
+    }
+
+    /// The most frequent value, mostly of use in multiset mode.
+    /// Ties go to the smallest of the values, and an empty tree has no mode.
+    pub fn mode(&self) -> Option<T> {
+        let mut best: Option<(&T, usize)> = None;
+        for (v, n) in self.iter_groups() {
+            // Values arrive in ascending order, so only a strictly
+            // higher count displaces the current best.
+            if best.is_none_or(|(_, most)| n > most) {
+                best = Some((v, n));
+            }
+        }
+        best.map(|(v, _)| v.clone())
+    }
+
+    /// The smallest value in the tree, in O(height).
+    /// Selecting by subtree size follows the left spine, but steps past tombstones.
+    pub fn min(&self) -> Option<T> {
+        self.select(0).cloned()
+    }
+
+    /// The largest value in the tree, in O(height).
+    pub fn max(&self) -> Option<T> {
+        self.select(self.len().checked_sub(1)?).cloned()
+    }
+
+    /// Remove and return the smallest value, taking one copy at a time in multiset mode.
+    /// Goes through `remove`, so the tree is relinked and rebalanced as usual.
+    pub fn remove_min(&mut self) -> Option<T> {
+        let min = self.min()?;
+        self.remove(min).ok()
+    }
+
+    /// Remove and return the largest value.
+    pub fn remove_max(&mut self) -> Option<T> {
+        let max = self.max()?;
+        self.remove(max).ok()
+    }
+
+    /// The `k`-th smallest value, counting from 0, so `kth_smallest(0)` is the minimum.
+    /// Each copy counts in multiset mode. Found in O(height) from the subtree sizes.
+    pub fn kth_smallest(&self, k: usize) -> Option<T> {
+        self.select(k).cloned()
+    }
+
+    /// The values from the root down through left children to the smallest value.
+    /// Here and in `right_spine`, nodes removed in lazy deletion mode are left out.
+    pub fn left_spine(&self) -> Vec<T> {
+        self.spine(|t| &t.left)
+    }
+
+    /// The values from the root down through right children to the largest value.
+    pub fn right_spine(&self) -> Vec<T> {
+        self.spine(|t| &t.right)
+    }
+
+    fn spine<F>(&self, next: F) -> Vec<T> where F: Fn(&Node<T>) -> &Option<Box<Node<T>>> {
+        let mut values = Vec::new();
+        let mut node = if self.root.val.is_some() { Some(&self.root) } else { None };
+        while let Some(t) = node {
+            if t.count > 0 {
+                values.push(t.val.clone().unwrap());
+            }
+            node = next(t).as_deref();
+        }
+        values
+    }
+
+    /// Every path from the root down to a leaf, as the values along it,
+    /// with the paths ordered from the leftmost leaf to the rightmost.
+    /// Multiset copies are only listed once per node. In lazy deletion mode
+    /// the leaves are those given by `leaves`, and removed values are left out.
+    pub fn root_to_leaf_paths(&self) -> Vec<Vec<T>> {
+        let mut paths = Vec::new();
+        let mut stack = Stack::new();
+        if self.root.size > 0 {
+            stack.push((&self.root, Vec::new()));
+        }
+
+        while let Some((t, mut path)) = stack.pop() {
+            if t.count > 0 {
+                path.push(t.val.clone().unwrap());
+            }
+            if t.live_children().next().is_none() {
+                paths.push(path);
+                continue;
+            }
+            // Right first, so the left side is popped first.
+            for child in t.live_children().rev() {
+                stack.push((child, path.clone()));
+            }
+        }
+
+        paths
+    }
+
+    /// Insert every value from `other` into this tree, skipping any already present.
+    /// In multiset mode each copy is added as `insert` would.
+    pub fn merge(&mut self, other: BinaryTree<T, C, A>) {
+        self.extend(other);
+    }
+
+    /// Compare against another tree, giving `(added, removed)`: the values only in
+    /// `other`, and the values only in this tree, both in ascending order.
+    /// In multisets each extra copy counts as added or removed.
+    pub fn diff(&self, other: &BinaryTree<T, C, A>) -> (Vec<T>, Vec<T>) {
+        let mut ours = self.iter();
+        let mut theirs = other.iter();
+        let mut a = ours.next();
+        let mut b = theirs.next();
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+
+        loop {
+            match (a, b) {
+                (Some(x), Some(y)) => match self.compare(x, y) {
+                    Ordering::Less => {
+                        removed.push(x.clone());
+                        a = ours.next();
+                    },
+                    Ordering::Greater => {
+                        added.push(y.clone());
+                        b = theirs.next();
+                    },
+                    Ordering::Equal => {
+                        a = ours.next();
+                        b = theirs.next();
+                    },
+                },
+                (Some(x), None) => {
+                    removed.push(x.clone());
+                    a = ours.next();
+                },
+                (None, Some(y)) => {
+                    added.push(y.clone());
+                    b = theirs.next();
+                },
+                (None, None) => break,
+            }
+        }
+
+        (added, removed)
+    }
+
+    // Collect in order the values satisfying both bounds, as for `range_nodes`.
+    fn collect_range<L, H>(&self, above: L, below: H) -> Vec<T>
+        where L: Fn(&T) -> bool, H: Fn(&T) -> bool
+    {
+        self.range_nodes(above, below)
+            .flat_map(|t| t.values_here())
+            .cloned()
+            .collect()
+    }
+}
+
+impl<T> Node<T> where T: Clone {
+    // Update `best` with the longest path found in this subtree, as its length and ends.
+    // Returns the distance down to the deepest node in the subtree, along with its value.
+    fn diameter_walk<'a>(&'a self, best: &mut Option<(usize, &'a T, &'a T)>) -> (usize, &'a T) {
+        let v = self.val.as_ref().unwrap();
+        let left = self.left.as_ref().map(|t| t.diameter_walk(best));
+        let right = self.right.as_ref().map(|t| t.diameter_walk(best));
+
+        let (through, deepest) = match (left, right) {
+            (None, None) => ((0, v, v), (0, v)),
+            (Some((h, x)), None) => ((h + 1, x, v), (h + 1, x)),
+            (None, Some((h, y))) => ((h + 1, v, y), (h + 1, y)),
+            (Some((hl, x)), Some((hr, y))) => {
+                let deepest = if hr > hl { (hr + 1, y) } else { (hl + 1, x) };
+                ((hl + hr + 2, x, y), deepest)
+            },
+        };
+
+        if best.is_none_or(|(len, _, _)| through.0 > len) {
+            *best = Some(through);
+        }
+        deepest
+    }
+
+    // Rebuild the subtree rooted at the node holding `root_val`, as for
+    // `BinaryTree::rebalance_subtree`.
+    fn rebalance_subtree<C, A>(&mut self, root_val: T, order: &C, alloc: &mut A) -> bool
+        where C: Compare<T>, A: NodeAlloc<Node<T>>
+    {
+        if self.val.is_none() {
+            return false;
+        }
+
+        let found = match order.compare(&root_val, self.val.as_ref().unwrap()) {
+            Ordering::Less => self.left.as_mut().is_some_and(|t| t.rebalance_subtree(root_val, order, alloc)),
+            Ordering::Greater => self.right.as_mut().is_some_and(|t| t.rebalance_subtree(root_val, order, alloc)),
+            Ordering::Equal => {
+                let groups = self.groups();
+                self.release(alloc);
+                *self = Node::balanced(groups, alloc);
+                return true;
+            },
+        };
+
+        // The subtree below may have got shorter.
+        if found {
+            self.update();
+        }
+        found
+    }
+
+    // The distinct values in order, with their further copies.
+    fn groups(&self) -> Vec<(T, Vec<T>)> {
+        let mut nodes = Iter::new(self);
+        iter::from_fn(|| nodes.find_node(|n| n.count > 0))
+            .map(|n| (n.val.clone().unwrap(), n.copies.clone()))
+            .collect()
+    }
+}
+
+impl<T, C, A> BinaryTree<T, C, A> where T: Integer, C: Compare<T>, A: NodeAlloc<Node<T>> {
+    /// Sum all values in the tree, or `None` if the sum overflows.
+    pub fn checked_sum(&self) -> Option<T> {
+        self.iter().try_fold(T::ZERO, |acc, &x| acc.checked_add(x))
+    }
+
+    /// Sum all values in the tree, clamping at the numeric bounds instead of overflowing.
+    /// Values are added in ascending order, saturating at each step, so a tree
+    /// mixing large negative and positive values may not give the clamped exact sum.
+    pub fn saturating_sum(&self) -> T {
+        self.iter().fold(T::ZERO, |acc, &x| acc.saturating_add(x))
+    }
+}
+
+impl<T, C, A> BinaryTree<T, C, A> where T: fmt::Display, C: Compare<T>, A: NodeAlloc<Node<T>> {
+    /// Render the tree one level per line, stopping after `max_nodes` nodes
+    /// in level order. If any were left out, a final line says how many.
+    /// Nodes removed in lazy deletion mode aren't shown or counted.
+    pub fn pretty_truncated(&self, max_nodes: usize) -> String {
+        if self.root.size == 0 {
+            return String::from("<empty>\n");
+        }
+
+        let mut out = String::new();
+        let mut shown = 0;
+        let mut level = vec![&self.root];
+
+        while !level.is_empty() && shown < max_nodes {
+            let mut next = Vec::new();
+            let mut line = Vec::new();
+            for t in level {
+                if shown + line.len() == max_nodes {
+                    break;
+                }
+                if t.count > 0 {
+                    line.push(t.val.as_ref().unwrap().to_string());
+                }
+                next.extend(t.live_children());
+            }
+            shown += line.len();
+            if !line.is_empty() {
+                out.push_str(&line.join(" "));
+                out.push('\n');
+            }
+            level = next;
+        }
+
+        let mut nodes = self.iter();
+        let omitted = iter::from_fn(|| nodes.find_node(|n| n.count > 0)).count() - shown;
+        if omitted > 0 {
+            out.push_str(&format!("... {} more\n", omitted));
+        }
+        out
+    }
+
+    /// Write out the exact shape of the tree in a compact pre-order form.
+    /// Each node is its value, followed by `(left,right)` if it has any children,
+    /// with `#` standing in for a missing child, e.g. `5(3(1,#),8)`.
+    /// An empty tree is just `#`. Copies in multiset mode aren't recorded.
+    /// Nodes removed in lazy deletion mode can't be written, so while there are
+    /// any this writes the shape `compact` would leave instead.
+    pub fn serialize_compact(&self) -> String {
+        let mut out = String::new();
+        let mut nodes = self.iter();
+        if self.root.size == 0 {
+            out.push('#');
+        } else if iter::from_fn(|| nodes.next_node()).any(|n| n.count == 0) {
+            let values: Vec<&T> = self.iter_groups().map(|(v, _)| v).collect();
+            Node::write_balanced(&values, &mut out);
+        } else {
+            self.root.write_compact(&mut out);
+        }
+        out
+    }
+}
+
+impl<T> Node<T> where T: fmt::Display {
+    // Write the values, in order, in the shape `Node::balanced` gives them.
+    fn write_balanced(values: &[&T], out: &mut String) {
+        let mid = values.len() / 2;
+        out.push_str(&values[mid].to_string());
+        let (left, right) = (&values[..mid], &values[mid + 1..]);
+        if left.is_empty() && right.is_empty() {
+            return;
+        }
+
+        out.push('(');
+        for (i, side) in [left, right].iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            if side.is_empty() {
+                out.push('#');
+            } else {
+                Node::write_balanced(side, out);
+            }
+        }
+        out.push(')');
+    }
+
+    fn write_compact(&self, out: &mut String) {
+        out.push_str(&self.val.as_ref().unwrap().to_string());
+        if self.left.is_none() && self.right.is_none() {
+            return;
+        }
+
+        out.push('(');
+        for (i, child) in [&self.left, &self.right].iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            match **child {
+                None => out.push('#'),
+                Some(ref t) => t.write_compact(out),
+            }
+        }
+        out.push(')');
+    }
+}
+
+impl<T> BinaryTree<T> where T: Ord + FromStr {
+    /// Rebuild a tree written by `serialize_compact`, with exactly the same shape.
+    /// Fails if the string is malformed, or its values aren't in search tree order.
+    pub fn deserialize_compact(s: &str) -> Result<BinaryTree<T>, ParseError> {
+        let mut parser = CompactParser{ s, pos: 0 };
+        let root = match parser.node()? {
+            None => Node::new(),
+            Some(t) => *t,
+        };
+
+        if parser.pos < s.len() {
+            return Err(ParseError::UnexpectedChar(parser.pos));
+        }
+        let tree = BinaryTree{ root, settings: Settings::default(), alloc: BoxAlloc };
+        if tree.first_unordered_pair().is_some() {
+            return Err(ParseError::Unordered);
+        }
+        Ok(tree)
+    }
+}
+
+/// Trees are equal when they hold the same values by their order, however they're shaped.
+/// Use `structurally_eq` to compare the shapes as well.
+impl<T, C, A> PartialEq for BinaryTree<T, C, A> where C: Compare<T>, A: NodeAlloc<Node<T>> {
+    fn eq(&self, other: &BinaryTree<T, C, A>) -> bool {
+        self.len() == other.len()
+            && self.iter().zip(other.iter()).all(|(a, b)| self.compare(a, b) == Ordering::Equal)
+    }
+}
+
+impl<T, C, A> Eq for BinaryTree<T, C, A> where C: Compare<T>, A: NodeAlloc<Node<T>> {}
+
+impl<T, A> Default for BinaryTree<T, Natural, A> where T: Ord, A: NodeAlloc<Node<T>> + Default {
+    fn default() -> BinaryTree<T, Natural, A> {
+        BinaryTree::new().with_alloc(A::default())
+    }
+}
+
+/// Draws the tree on its side, one node per line indented by its depth,
+/// with right subtrees above their parent and left subtrees below, e.g.
+///
+/// ```text
+///     8
+/// 5
+///         4
+///     3
+/// ```
+///
+/// Nodes removed in lazy deletion mode leave their lines out, but the
+/// values below them keep their depth.
+impl<T, C, A> fmt::Display for BinaryTree<T, C, A> where T: fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.root.size == 0 {
+            return writeln!(f, "<empty>");
+        }
+
+        // Reverse in-order. Nodes are pushed twice, the second time marked
+        // as ready to be written once their right subtree is done.
+        let mut stack = Stack::new();
+        stack.push((&self.root, 0, false));
+        while let Some((t, depth, ready)) = stack.pop() {
+            if ready {
+                if t.count > 0 {
+                    writeln!(f, "{}{}", "    ".repeat(depth), t.val.as_ref().unwrap())?;
+                }
+                continue;
+            }
+            if let Some(l) = Node::live(&t.left) {
+                stack.push((l, depth + 1, false));
+            }
+            stack.push((t, depth, true));
+            if let Some(r) = Node::live(&t.right) {
+                stack.push((r, depth + 1, false));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Not derived, as comparators are usually closures, which can't be printed.
+impl<T, C, A> fmt::Debug for BinaryTree<T, C, A> where T: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BinaryTree")
+            .field("root", &self.root)
+            .field("multiset", &self.settings.multiset)
+            .field("lazy", &self.settings.lazy)
+            .field("balanced", &self.settings.balanced)
+            .finish()
+    }
+}
+
+/// Serializes as the values in ascending order, repeated once per copy,
+/// rather than as the layout of the nodes.
+#[cfg(feature = "serde")]
+impl<T, C, A> serde::Serialize for BinaryTree<T, C, A> where T: serde::Serialize, C: Compare<T>, A: NodeAlloc<Node<T>> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+/// Deserializes a sequence of values in any order, building a height-balanced
+/// tree from them. Duplicates are dropped, just as `insert` would reject them.
+#[cfg(feature = "serde")]
+impl<'de, T, A> serde::Deserialize<'de> for BinaryTree<T, Natural, A>
+    where T: Ord + serde::Deserialize<'de>, A: NodeAlloc<Node<T>> + Default
+{
+    fn deserialize<D>(deserializer: D) -> Result<BinaryTree<T, Natural, A>, D::Error>
+        where D: serde::Deserializer<'de>
+    {
+        let mut values = Vec::<T>::deserialize(deserializer)?;
+        values.sort();
+        values.dedup();
+        let groups = values.into_iter().map(|v| (v, Vec::new())).collect();
+        let mut t = BinaryTree::new().with_alloc(A::default());
+        t.root = Node::balanced(groups, &mut t.alloc);
+        Ok(t)
+    }
+}
+
+/// An empty node, as left in the boxes kept by `PoolAlloc`.
+impl<T> Default for Node<T> {
+    fn default() -> Node<T> {
+        Node::new()
+    }
+}
+
+/// Frees the nodes one at a time from a work stack, rather than letting each box
+/// drop its children recursively, so dropping a very deep tree can't overflow.
+impl<T> Drop for Node<T> {
+    fn drop(&mut self) {
+        if self.left.is_none() && self.right.is_none() {
+            return;
+        }
+        let mut stack = Stack::new();
+        self.push_children(&mut stack);
+
+        // Each node is detached from its children before it drops, so its own
+        // drop returns straight away, and the one stack serves the whole tree.
+        while let Some(mut t) = stack.pop() {
+            t.push_children(&mut stack);
+        }
+    }
+}
+
+/// Collect values into a tree by inserting them in turn.
+/// Duplicates are dropped, just as `insert` would reject them.
+impl<T, A> iter::FromIterator<T> for BinaryTree<T, Natural, A>
+    where T: Ord, A: NodeAlloc<Node<T>> + Default
+{
+    fn from_iter<I>(iter: I) -> BinaryTree<T, Natural, A> where I: IntoIterator<Item = T> {
+        let mut t = BinaryTree::new().with_alloc(A::default());
+        t.extend(iter);
+        t
+    }
+}
+
+/// Insert each value in turn, skipping any that `insert` rejects.
+impl<T, C, A> Extend<T> for BinaryTree<T, C, A> where C: Compare<T>, A: NodeAlloc<Node<T>> {
+    fn extend<I>(&mut self, iter: I) where I: IntoIterator<Item = T> {
+        for val in iter {
+            let _ = self.insert(val);
+        }
+    }
+}
+
+// Recursive descent parser for the form written by `serialize_compact`.
+struct CompactParser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> CompactParser<'a> {
+    fn node<T>(&mut self) -> Result<Option<Box<Node<T>>>, ParseError>
+        where T: FromStr
+    {
+        if self.s[self.pos..].starts_with('#') {
+            self.pos += 1;
+            return Ok(None);
+        }
+
+        let start = self.pos;
+        let len = self.s[start..].find(['(', ',', ')']).unwrap_or(self.s.len() - start);
+        if len == 0 {
+            return Err(self.unexpected());
+        }
+        self.pos += len;
+
+        let val = self.s[start..self.pos].parse().map_err(|_| ParseError::InvalidValue(start))?;
+        let mut t = Node::new();
+        t.val = Some(val);
+        t.count = 1;
+
+        if self.s[self.pos..].starts_with('(') {
+            self.pos += 1;
+            t.left = self.node()?;
+            self.expect(',')?;
+            t.right = self.node()?;
+            self.expect(')')?;
+        }
+        t.update();
+
+        Ok(Some(Box::new(t)))
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ParseError> {
+        if self.s[self.pos..].starts_with(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn unexpected(&self) -> ParseError {
+        if self.pos == self.s.len() {
+            ParseError::UnexpectedEnd
+        } else {
+            ParseError::UnexpectedChar(self.pos)
+        }
+    }
+}
+
+/// Error from `deserialize_compact` when the input isn't a valid tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The input stopped part way through a tree.
+    UnexpectedEnd,
+    /// Unexpected character at the given byte offset.
+    UnexpectedChar(usize),
+    /// The value starting at the given byte offset couldn't be parsed.
+    InvalidValue(usize),
+    /// The values don't form a valid search tree.
+    Unordered,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            ParseError::UnexpectedChar(pos) => write!(f, "unexpected character at offset {}", pos),
+            ParseError::InvalidValue(pos) => write!(f, "invalid value at offset {}", pos),
+            ParseError::Unordered => write!(f, "values are not in search tree order"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// The `k`-th smallest value across both trees, counting from 0,
+/// with values in both trees counted once for each.
+/// Walks the two trees in order together, stopping at the `k`-th value.
+pub fn kth_smallest_across<T, C, A>(a: &BinaryTree<T, C, A>, b: &BinaryTree<T, C, A>, k: usize) -> Option<T>
+    where T: Clone, C: Compare<T>, A: NodeAlloc<Node<T>>
+{
+    let mut xs = a.iter().peekable();
+    let mut ys = b.iter().peekable();
+    let mut merged = iter::from_fn(|| {
+        match (xs.peek(), ys.peek()) {
+            (Some(x), Some(y)) if a.compare(y, x) == Ordering::Less => ys.next(),
+            (Some(_), _) => xs.next(),
+            (None, _) => ys.next(),
+        }
+    });
+    merged.nth(k).cloned()
+}
+
+/// Count the pairs of items which are out of order, i.e. where a larger item comes first.
+/// Equal items are never counted as out of order with each other.
+/// Each item is inserted into a tree of those before it, which gives
+/// how many earlier items are larger in O(height) from the subtree sizes.
+pub fn count_inversions<T>(items: &[T]) -> usize where T: Ord + Clone {
+    let mut seen = BinaryTree::new_multiset();
+    let mut inversions = 0;
+    for item in items {
+        inversions += seen.count_greater(item);
+        let _ = seen.insert(item.clone());
+    }
+    inversions
+}
+
+/// Where an item produced by `BinaryTree::merge_with` came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeSide<T> {
+    /// Only in the tree.
+    Tree(T),
+    /// Only in the other source.
+    Other(T),
+    /// Equal items from the tree and the other source, in that order.
+    Both(T, T),
+}
+
+/// Error from `try_from_sorted_slice` when the values aren't strictly ascending.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortError {
+    /// The value at the given index is smaller than the one before it.
+    OutOfOrder(usize),
+    /// The value at the given index is the same as the one before it.
+    Duplicate(usize),
+}
+
+impl fmt::Display for SortError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SortError::OutOfOrder(i) => write!(f, "value at index {} is out of order", i),
+            SortError::Duplicate(i) => write!(f, "value at index {} is a duplicate", i),
+        }
+    }
+}
+
+impl Error for SortError {}
+
+/// Error from `checked_height` when the tree is deeper than allowed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepthOverflow {
+    /// The depth limit which was exceeded.
+    pub limit: usize,
+}
+
+impl fmt::Display for DepthOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tree is deeper than the limit of {}", self.limit)
+    }
+}
+
+impl Error for DepthOverflow {}
+
+/// In-order iterator over the values of a tree.
+/// Keeps an explicit stack of the nodes still to visit,
+/// so never recurses however skewed the tree is.
+pub struct Iter<'a, T: 'a> {
+    stack: Stack<&'a Node<T>>,
+    // The node last stepped to, and how many of its copies have been yielded.
+    current: Option<&'a Node<T>>,
+    yielded: usize,
+    // Number of nodes on the stack now, and the most there have been.
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<'a, T> Iter<'a, T> {
+    fn new(tree: &'a Node<T>) -> Iter<'a, T> {
+        let mut iter = Iter{ stack: Stack::new(), current: None, yielded: 0, depth: 0, max_depth: 0 };
+        if tree.val.is_some() {
+            iter.push_left(tree);
+        }
+        iter
+    }
+
+    /// The most nodes the iterator has held on its internal stack at once so far.
+    /// The stack holds a path down the tree, so after a full iteration this is
+    /// one more than the height of the tree, showing the O(height) space used.
+    pub fn max_stack_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    // Push the node and all its left descendants onto the stack.
+    fn push_left(&mut self, tree: &'a Node<T>) {
+        let mut node = Some(tree);
+        while let Some(t) = node {
+            self.stack.push(t);
+            node = t.left.as_deref();
+            self.depth += 1;
+        }
+        self.max_depth = cmp::max(self.max_depth, self.depth);
+    }
+
+    // Step to the next node in order.
+    fn next_node(&mut self) -> Option<&'a Node<T>> {
+        let node = self.stack.pop()?;
+        self.depth -= 1;
+        if let Some(ref t) = node.right {
+            self.push_left(t);
+        }
+        Some(node)
+    }
+
+    // Step through nodes in order until one matches.
+    fn find_node<F>(&mut self, f: F) -> Option<&'a Node<T>> where F: Fn(&Node<T>) -> bool {
+        while let Some(node) = self.next_node() {
+            if f(node) {
+                return Some(node);
+            }
+        }
+        None
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(node) = self.current {
+                if self.yielded < node.count {
+                    self.yielded += 1;
+                    return Some(node.value_at(self.yielded - 1));
+                }
+            }
+            self.current = Some(self.next_node()?);
+            self.yielded = 0;
+        }
+    }
+}
+
+/// In-order iterator which temporarily reverses the links of the tree it walks.
+/// See `BinaryTree::iter_in_place`.
+pub struct InPlaceIter<'a, T: 'a, C: 'a = Natural, A: 'a = BoxAlloc>
+    where C: Compare<T>, A: NodeAlloc<Node<T>>
+{
+    tree: &'a mut BinaryTree<T, C, A>,
+    // The node being visited, with the path back up to the root hanging off
+    // `up`, in which each node's child slot on the way down holds its parent.
+    // `focus` is `None` once the whole tree has been visited and put back.
+    focus: Option<Box<Node<T>>>,
+    up: Option<Box<Node<T>>>,
+    // How many copies of the focus's value have been yielded.
+    yielded: usize,
+}
+
+impl<'a, T, C, A> InPlaceIter<'a, T, C, A> where C: Compare<T>, A: NodeAlloc<Node<T>> {
+    /// Step to the next value in order, lending a reference to it which lasts
+    /// until the iterator moves on, so values needn't be cloned.
+    pub fn next_ref(&mut self) -> Option<&T> {
+        while self.yielded == self.focus.as_ref()?.count {
+            self.yielded = 0;
+            self.advance();
+        }
+        self.yielded += 1;
+        self.focus.as_ref().map(|t| t.value_at(self.yielded - 1))
+    }
+
+    // Step down from the focus to its leftmost descendant.
+    fn descend_left(&mut self) {
+        let mut focus = self.focus.take().unwrap();
+        while let Some(child) = focus.left.take() {
+            focus.left = self.up.take();
+            self.up = Some(focus);
+            focus = child;
+        }
+        self.focus = Some(focus);
+    }
+
+    // Step up from the focus to its parent, relinking the focus under it.
+    // Returns whether the focus was the parent's left child.
+    fn ascend(&mut self) -> bool {
+        let child = self.focus.take().unwrap();
+        let mut parent = self.up.take().unwrap();
+        let (c, p) = (child.val.as_ref().unwrap(), parent.val.as_ref().unwrap());
+        let from_left = self.tree.compare(c, p) == Ordering::Less;
+        let slot = if from_left { &mut parent.left } else { &mut parent.right };
+        self.up = slot.replace(child);
+        self.focus = Some(parent);
+        from_left
+    }
+
+    // Move to the next node in order, which is either the leftmost node
+    // of the right subtree, or the first ancestor entered from its left.
+    // Puts the tree back together after the last node.
+    fn advance(&mut self) {
+        let focus = self.focus.as_mut().unwrap();
+        if let Some(child) = focus.right.take() {
+            focus.right = self.up.take();
+            self.up = Some(self.focus.take().unwrap());
+            self.focus = Some(child);
+            self.descend_left();
+            return;
+        }
+
+        while self.up.is_some() {
+            if self.ascend() {
+                return;
+            }
+        }
+        self.restore();
+    }
+
+    fn restore(&mut self) {
+        if self.focus.is_none() {
+            return;
+        }
+        while self.up.is_some() {
+            self.ascend();
+        }
+        let root = self.focus.take().unwrap();
+        self.tree.root = self.tree.alloc.free(root);
+    }
+}
+
+impl<'a, T, C, A> Iterator for InPlaceIter<'a, T, C, A> where T: Clone, C: Compare<T>, A: NodeAlloc<Node<T>> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_ref().cloned()
+    }
+}
+
+/// Stopping part way through still puts the tree back together.
+impl<'a, T, C, A> Drop for InPlaceIter<'a, T, C, A> where C: Compare<T>, A: NodeAlloc<Node<T>> {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+/// Consuming in-order iterator over the values of a tree.
+/// Each node is freed as soon as its values have been taken.
+pub struct IntoIter<T> {
+    stack: Stack<Node<T>>,
+    // The copies of the value last stepped to which are still to be yielded.
+    values: iter::Chain<option::IntoIter<T>, vec::IntoIter<T>>,
+}
+
+impl<T> IntoIter<T> {
+    // Push the node and all its left descendants onto the stack,
+    // detaching each from its parent.
+    fn push_left(&mut self, mut tree: Node<T>) {
+        loop {
+            let left = tree.left.take();
+            self.stack.push(tree);
+            match left {
+                None => break,
+                Some(t) => tree = *t,
+            }
+        }
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(val) = self.values.next() {
+                return Some(val);
+            }
+
+            let mut node = self.stack.pop()?;
+            if let Some(r) = node.right.take() {
+                self.push_left(*r);
+            }
+            // The values are moved out, skipping a tombstone's.
+            let live = if node.count > 0 { node.val.take() } else { None };
+            self.values = live.into_iter().chain(mem::take(&mut node.copies));
+        }
+    }
+}
+
+/// Consume the tree, yielding its values in ascending order.
+impl<T, C, A> IntoIterator for BinaryTree<T, C, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        let values = None.into_iter().chain(Vec::new());
+        let mut iter = IntoIter{ stack: Stack::new(), values };
+        if self.root.val.is_some() {
+            iter.push_left(self.root);
+        }
+        iter
+    }
+}
+
+// Constructors for trees with any allocator which has a `Default`, under the
+// same names as the inherent ones, which only make trees with `BoxAlloc`.
+// They let the tests below build whichever kind of tree `BinaryTree` names.
+#[cfg(test)]
+trait TestTree<T, A>: Sized {
+    fn new() -> Self where T: Ord;
+    fn new_multiset() -> Self where T: Ord;
+    fn new_lazy() -> Self where T: Ord;
+    fn new_balanced() -> Self where T: Ord;
+    fn from_sorted_slice(values: &[T]) -> Self where T: Ord + Clone;
+    fn try_from_sorted_slice(values: &[T]) -> Result<Self, SortError> where T: Ord + Clone;
+    fn deserialize_compact(s: &str) -> Result<Self, ParseError> where T: Ord + FromStr;
+    fn with_comparator<F>(compare: F) -> BinaryTree<T, F, A> where F: Fn(&T, &T) -> Ordering;
+    fn from_builder<C>(builder: Builder<C>) -> BinaryTree<T, C, A> where C: Compare<T>;
+}
+
+#[cfg(test)]
+impl<T, A> TestTree<T, A> for BinaryTree<T, Natural, A> where A: NodeAlloc<Node<T>> + Default {
+    fn new() -> Self where T: Ord {
+        BinaryTree::<T>::new().with_alloc(A::default())
+    }
+
+    fn new_multiset() -> Self where T: Ord {
+        BinaryTree::<T>::new_multiset().with_alloc(A::default())
+    }
+
+    fn new_lazy() -> Self where T: Ord {
+        BinaryTree::<T>::new_lazy().with_alloc(A::default())
+    }
+
+    fn new_balanced() -> Self where T: Ord {
+        BinaryTree::<T>::new_balanced().with_alloc(A::default())
+    }
+
+    fn from_sorted_slice(values: &[T]) -> Self where T: Ord + Clone {
+        BinaryTree::<T>::from_sorted_slice(values).with_alloc(A::default())
+    }
+
+    fn try_from_sorted_slice(values: &[T]) -> Result<Self, SortError> where T: Ord + Clone {
+        BinaryTree::<T>::try_from_sorted_slice(values).map(|t| t.with_alloc(A::default()))
+    }
+
+    fn deserialize_compact(s: &str) -> Result<Self, ParseError> where T: Ord + FromStr {
+        BinaryTree::<T>::deserialize_compact(s).map(|t| t.with_alloc(A::default()))
+    }
+
+    fn with_comparator<F>(compare: F) -> BinaryTree<T, F, A> where F: Fn(&T, &T) -> Ordering {
+        BinaryTree::<T>::with_comparator(compare).with_alloc(A::default())
+    }
+
+    fn from_builder<C>(builder: Builder<C>) -> BinaryTree<T, C, A> where C: Compare<T> {
+        builder.build().with_alloc(A::default())
+    }
+}
+
+// The tests, run once in a module for each node allocation strategy,
+// where `BinaryTree` stands for a tree using that strategy.
+#[cfg(test)]
+macro_rules! tree_tests { () => {
+
+// Depth of the skewed trees used to check iterative algorithms.
+// Bounded by the recursion in insert and remove themselves.
+const DEEP: i32 = 2000;
+
+// Left-leaning chain of the values `0..n`, with `n - 1` at the root, which can be
+// far deeper than `DEEP` as it's linked up by hand rather than through `insert`.
+// Each node's size and levels are kept up to date as `insert` would leave them.
+fn left_chain(n: i32) -> BinaryTree<i32> {
+    let mut t = BinaryTree::<i32>::new();
+    assert!(t.insert(0).is_ok());
+    for x in 1..n {
+        let mut parent = BinaryTree::new();
+        assert!(parent.insert(x).is_ok());
+        parent.root.left = Some(Box::new(t.root));
+        parent.root.update();
+        t = parent;
+    }
+    t
+}
+
+// A tree of the given values, inserted in turn.
+fn tree_of(values: &[i32]) -> BinaryTree<i32> {
+    let mut t = BinaryTree::new();
+    for x in values {
+        assert!(t.insert(*x).is_ok());
+    }
+    t
+}
+
+// The tree many of the tests below work on:
+//        5
+//     3     8
+//   1   4     9
+//    2
+fn sample_tree() -> BinaryTree<i32> {
+    tree_of(&[5, 3, 8, 1, 4, 9, 2])
+}
+
+// `sample_tree` in lazy deletion mode, with 3 and 9 removed but still in place:
+//        5
+//    (3)     8
+//   1   4     (9)
+//    2
+fn lazy_sample_tree() -> BinaryTree<i32> {
+    let mut t = BinaryTree::new_lazy();
+    for x in &[5, 3, 8, 1, 4, 9, 2] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.remove(3), Ok(3));
+    assert_eq!(t.remove(9), Ok(9));
+    t
+}
+
+#[test]
+fn insert() {
+    let mut t = BinaryTree::<i32>::new();
+    assert!(t.insert(3).is_ok());
+    assert!(t.insert(5).is_ok());
+    assert_eq!(t.root.val.unwrap(), 3);
+    assert_eq!(t.root.right.as_ref().unwrap().val.unwrap(), 5);
+}
+
+#[test]
+fn duplicate() {
+    let mut t = BinaryTree::<i32>::new();
+    assert!(t.insert(3).is_ok());
+    assert!(t.insert(3).is_err());
+    assert!(t.insert(3).is_err());
+    assert!(t.insert(5).is_ok());
+    assert!(t.insert(5).is_err());
+    assert!(t.insert(3).is_err());
     assert!(t.insert(5).is_err());
 }
 
 #[test]
-fn remove_no_children() {
+fn len() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.len(), 0);
+    assert!(t.is_empty());
+
+    for x in &[5, 3, 8, 1] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.len(), 4);
+    assert!(!t.is_empty());
+
+    // Failed inserts and removes leave it alone.
+    assert!(t.insert(3).is_err());
+    assert_eq!(t.remove(4), Err(4));
+    assert_eq!(t.len(), 4);
+
+    for x in &[5, 1, 3, 8] {
+        assert!(t.remove(*x).is_ok());
+    }
+    assert_eq!(t.len(), 0);
+    assert!(t.is_empty());
+    assert_eq!(t.remove(5), Err(5));
+    assert_eq!(t.len(), 0);
+
+    assert!(t.insert(2).is_ok());
+    assert_eq!(t.len(), 1);
+}
+
+#[test]
+fn clear() {
+    let mut t = BinaryTree::<i32>::new_multiset();
+    for x in &[5, 3, 8, 3] {
+        assert!(t.insert(*x).is_ok());
+    }
+    t.clear();
+    assert!(t.is_empty());
+    assert_eq!(t.height(), 0);
+    assert_eq!(t.iter().count(), 0);
+    assert!(!t.contains(5));
+
+    // Behaves like a fresh tree, still in multiset mode.
+    assert!(t.insert(4).is_ok());
+    assert!(t.insert(4).is_ok());
+    assert_eq!(t.len(), 2);
+    assert_eq!(t.to_sorted_vec(), vec![4, 4]);
+}
+
+#[test]
+fn from_iter() {
+    let t: BinaryTree<i32> = vec![5, 3, 8, 1, 3, 8, 8].into_iter().collect();
+    assert_eq!(t.len(), 4);
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(), vec![1, 3, 5, 8]);
+
+    let e: BinaryTree<i32> = iter::empty().collect();
+    assert!(e.is_empty());
+}
+
+#[test]
+fn extend() {
+    let mut t: BinaryTree<i32> = vec![5, 3, 8].into_iter().collect();
+    t.extend(vec![1, 3, 9, 5, 4]);
+    assert_eq!(t.to_sorted_vec(), vec![1, 3, 4, 5, 8, 9]);
+
+    // The existing root stays where it was.
+    assert_eq!(t.root.val, Some(5));
+    t.extend(iter::empty());
+    assert_eq!(t.len(), 6);
+}
+
+#[test]
+fn to_sorted_vec() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.to_sorted_vec(), Vec::<i32>::new());
+
+    for x in &[7, 2, 9, 4, 1] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.to_sorted_vec(), vec![1, 2, 4, 7, 9]);
+
+    let mut m = BinaryTree::<i32>::new_multiset();
+    for x in &[3, 1, 3, 2, 1] {
+        assert!(m.insert(*x).is_ok());
+    }
+    assert_eq!(m.to_sorted_vec(), vec![1, 1, 2, 3, 3]);
+}
+
+#[test]
+fn remove_no_children() {
+    let mut t = BinaryTree::<i32>::new();
+    // Set the tree up how we want.
+    assert!(t.insert(3).is_ok());
+    assert!(t.insert(5).is_ok());
+
+    // Check it's how we expect.
+    assert_eq!(t.root.val.unwrap(), 3);
+    assert_eq!(t.root.right.as_ref().unwrap().val.unwrap(), 5);
+
+    // Remove the leaf node.
+    assert!(t.remove(5).is_ok());
+
+    // Check the root no longer points anywhere.
+    assert!(t.root.left.is_none());
+    assert!(t.root.right.is_none());
+
+    // Remove the root.
+    assert!(t.remove(3).is_ok());
+
+    // Check it's now empty.
+    assert!(t.root.val.is_none());
+}
+
+#[test]
+fn remove_left_child() {
+    let mut t = BinaryTree::<i32>::new();
+    // Set the tree up how we want.
+    assert!(t.insert(3).is_ok());
+    assert!(t.insert(1).is_ok());
+
+    // Check it's how we expect.
+    assert_eq!(t.root.val.unwrap(), 3);
+    assert_eq!(t.root.left.as_ref().unwrap().val.unwrap(), 1);
+
+    // Remove the root node.
+    assert!(t.remove(3).is_ok());
+
+    // Check the root now contains 5, and nothing else.
+    assert_eq!(t.root.val.unwrap(), 1);
+    assert!(t.root.left.is_none());
+    assert!(t.root.right.is_none());
+}
+
+#[test]
+fn remove_right_child() {
+    let mut t = BinaryTree::<i32>::new();
+    // Set the tree up how we want.
+    assert!(t.insert(3).is_ok());
+    assert!(t.insert(5).is_ok());
+
+    // Check it's how we expect.
+    assert_eq!(t.root.val.unwrap(), 3);
+    assert_eq!(t.root.right.as_ref().unwrap().val.unwrap(), 5);
+
+    // Remove the root node.
+    assert!(t.remove(3).is_ok());
+
+    // Check the root now contains 5, and nothing else.
+    assert_eq!(t.root.val.unwrap(), 5);
+    assert!(t.root.left.is_none());
+    assert!(t.root.right.is_none());
+}
+
+#[test]
+fn remove_both_children() {
+    let mut t = BinaryTree::<i32>::new();
+    // Set the tree up how we want.
+    assert!(t.insert(3).is_ok());
+    assert!(t.insert(5).is_ok());
+    assert!(t.insert(1).is_ok());
+
+    // Check it's how we expect.
+    assert_eq!(t.root.val.unwrap(), 3);
+    assert_eq!(t.root.left.as_ref().unwrap().val.unwrap(), 1);
+    assert_eq!(t.root.right.as_ref().unwrap().val.unwrap(), 5);
+
+    // Remove the root node.
+    assert!(t.remove(3).is_ok());
+
+    // Check the tree is now how we expect.
+    assert_eq!(t.root.val.unwrap(), 1);
+    assert_eq!(t.root.right.as_ref().unwrap().val.unwrap(), 5);
+    assert!(t.root.left.is_none());
+}
+
+#[test]
+fn remove_nonexistent() {
+    let mut t = BinaryTree::<i32>::new();
+    assert!(t.remove(14).is_err());
+    assert!(t.insert(3).is_ok());
+    assert!(t.insert(5).is_ok());
+    assert!(t.insert(1).is_ok());
+    assert!(t.remove(14).is_err());
+    assert!(t.remove(0).is_err());
+    assert!(t.insert(14).is_ok());
+    assert!(t.remove(14).is_ok());
+}
+
+#[test]
+fn remove_left() {
+    let mut t = BinaryTree::<i32>::new();
+    // Set the tree up how we want.
+    assert!(t.insert(3).is_ok());
+    assert!(t.insert(5).is_ok());
+    assert!(t.insert(1).is_ok());
+
+    assert!(t.remove(1).is_ok());
+}
+
+#[test]
+fn remove_recursive_collapse() {
+    let mut t = BinaryTree::<i32>::new();
+    // Set the tree up how we want.
+    assert!(t.insert(5).is_ok());
+    assert!(t.insert(3).is_ok());
+    assert!(t.insert(1).is_ok());
+    assert!(t.insert(4).is_ok());
+    assert!(t.insert(8).is_ok());
+
+    // Check the tree is how we want.
+    assert_eq!(t.root.val.unwrap(), 5);
+    assert_eq!(t.root.left.as_ref().unwrap().val.unwrap(), 3);
+    assert_eq!(t.root.left.as_ref().unwrap().left.as_ref().unwrap().val.unwrap(), 1);
+    assert_eq!(t.root.left.as_ref().unwrap().right.as_ref().unwrap().val.unwrap(), 4);
+    assert_eq!(t.root.right.as_ref().unwrap().val.unwrap(), 8);
+
+    // Remove the root.
+    assert!(t.remove(5).is_ok());
+
+    // Check the tree is how we expect.
+    assert_eq!(t.root.val.unwrap(), 4);
+    assert_eq!(t.root.left.as_ref().unwrap().val.unwrap(), 3);
+    assert_eq!(t.root.left.as_ref().unwrap().left.as_ref().unwrap().val.unwrap(), 1);
+    assert!(t.root.left.as_ref().unwrap().right.is_none());
+    assert_eq!(t.root.right.as_ref().unwrap().val.unwrap(), 8);
+}
+
+#[test]
+fn remove_rightmost_with_left_child() {
+    let mut t = BinaryTree::<i32>::new();
+    //      5
+    //   2     8
+    //     4
+    //   3
+    for x in &[5, 2, 8, 4, 3] {
+        assert!(t.insert(*x).is_ok());
+    }
+
+    // 4 replaces the root, and its left child must move up in its place.
+    assert!(t.remove(5).is_ok());
+    assert_eq!(t.root.val.unwrap(), 4);
+    assert_eq!(t.root.left.as_ref().unwrap().right.as_ref().unwrap().val.unwrap(), 3);
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(), vec![2, 3, 4, 8]);
+}
+
+#[test]
+fn contains() {
+    let mut t = BinaryTree::<i32>::new();
+    assert!(!t.contains(5));
+
+    for x in &[5, 3, 8, 1, 4] {
+        assert!(t.insert(*x).is_ok());
+    }
+    for x in &[5, 3, 8, 1, 4] {
+        assert!(t.contains(*x));
+    }
+
+    // Searches ending at a missing child on either side.
+    assert!(!t.contains(0));
+    assert!(!t.contains(2));
+    assert!(!t.contains(9));
+    assert!(!t.contains(6));
+
+    // Lookups leave the tree alone.
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(), vec![1, 3, 4, 5, 8]);
+
+    assert_eq!(t.remove(3), Ok(3));
+    assert!(!t.contains(3));
+    assert!(t.contains(4));
+    assert_eq!(t.remove(5), Ok(5));
+    assert_eq!(t.remove(8), Ok(8));
+    assert_eq!(t.remove(1), Ok(1));
+    assert_eq!(t.remove(4), Ok(4));
+    assert!(!t.contains(4));
+}
+
+#[test]
+fn string_values() {
+    let mut t = BinaryTree::<String>::new();
+    for s in &["pear", "apple", "quince", "fig", "plum"] {
+        assert_eq!(t.insert(s.to_string()), Ok(()));
+    }
+    assert_eq!(t.insert("fig".to_string()), Err("fig".to_string()));
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(),
+               vec!["apple", "fig", "pear", "plum", "quince"]);
+
+    // Removing the root, which has two children.
+    assert_eq!(t.remove("pear".to_string()), Ok("pear".to_string()));
+    assert_eq!(t.remove("apple".to_string()), Ok("apple".to_string()));
+    assert_eq!(t.remove("kiwi".to_string()), Err("kiwi".to_string()));
+    assert_eq!(t.len(), 3);
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(), vec!["fig", "plum", "quince"]);
+}
+
+#[test]
+fn non_clone_values() {
+    // Deliberately neither Copy nor Clone, so values can only be moved.
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Key(Vec<u8>);
+
+    let mut t = BinaryTree::<Key>::new();
+    for k in &[b"m", b"c", b"x", b"a", b"e"] {
+        assert_eq!(t.insert(Key(k.to_vec())), Ok(()));
+    }
+
+    // A rejected duplicate comes back to the caller.
+    assert_eq!(t.insert(Key(b"c".to_vec())), Err(Key(b"c".to_vec())));
+
+    // Removing moves the stored value out, from a leaf or from a node with two children.
+    assert_eq!(t.remove(Key(b"a".to_vec())), Ok(Key(b"a".to_vec())));
+    assert_eq!(t.remove(Key(b"m".to_vec())), Ok(Key(b"m".to_vec())));
+    assert_eq!(t.remove(Key(b"q".to_vec())), Err(Key(b"q".to_vec())));
+    assert!(t.contains(Key(b"x".to_vec())));
+    assert_eq!(t.iter().map(|k| k.0[0]).collect::<Vec<_>>(), b"cex".to_vec());
+
+    // Consuming the tree moves the values out.
+    assert_eq!(t.into_iter().collect::<Vec<_>>(),
+               vec![Key(b"c".to_vec()), Key(b"e".to_vec()), Key(b"x".to_vec())]);
+
+    // Multiset copies are kept whole, so each can be moved out again.
+    let mut m = BinaryTree::<Key>::new_multiset();
+    for k in &[b"b", b"a", b"b", b"b"] {
+        assert_eq!(m.insert(Key(k.to_vec())), Ok(()));
+    }
+    let mut in_place = m.iter_in_place();
+    let mut seen = Vec::new();
+    while let Some(k) = in_place.next_ref() {
+        seen.push(k.0[0]);
+    }
+    drop(in_place);
+    assert_eq!(seen, b"abbb".to_vec());
+
+    assert_eq!(m.remove(Key(b"b".to_vec())), Ok(Key(b"b".to_vec())));
+    assert_eq!(m.into_iter().map(|k| k.0[0]).collect::<Vec<_>>(), b"abb".to_vec());
+}
+
+#[test]
+fn with_comparator() {
+    let mut t = BinaryTree::<i32>::with_comparator(|a, b| b.cmp(a));
+    for x in &[5, 3, 8, 1, 4, 9] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.insert(4), Err(4));
+    assert_eq!(t.to_sorted_vec(), vec![9, 8, 5, 4, 3, 1]);
+    assert!(t.iter_in_place().eq(vec![9, 8, 5, 4, 3, 1]));
+    assert!(t.validate());
+
+    // "Smallest" follows the comparator.
+    assert_eq!(t.min(), Some(9));
+    assert_eq!(t.successor(5), Some(4));
+    assert_eq!(t.range(8, 3).cloned().collect::<Vec<_>>(), vec![8, 5, 4, 3]);
+
+    assert_eq!(t.remove(8), Ok(8));
+    assert!(!t.contains(8));
+    assert!(t.contains(9));
+    assert_eq!(t.to_sorted_vec(), vec![9, 5, 4, 3, 1]);
+
+    // A comparator capturing nothing leaves the tree `Send`.
+    let sender = ::std::thread::spawn(move || t.to_sorted_vec());
+    assert_eq!(sender.join().unwrap(), vec![9, 5, 4, 3, 1]);
+
+    // Comparing on one field only, so pairs sharing it are duplicates.
+    let mut by_key = BinaryTree::<(i32, &str)>::with_comparator(|a, b| a.0.cmp(&b.0));
+    assert!(by_key.insert((2, "b")).is_ok());
+    assert!(by_key.insert((1, "z")).is_ok());
+    assert_eq!(by_key.insert((2, "a")), Err((2, "a")));
+    assert_eq!(by_key.to_sorted_vec(), vec![(1, "z"), (2, "b")]);
+
+    // Comparators needn't be `Send`, here counting its calls through an `Rc`.
+    let calls = ::std::rc::Rc::new(::std::cell::Cell::new(0));
+    let counter = calls.clone();
+    let mut counted = BinaryTree::<i32>::with_comparator(move |a, b| {
+        counter.set(counter.get() + 1);
+        a.cmp(b)
+    });
+    assert!(counted.insert(2).is_ok());
+    assert!(counted.insert(1).is_ok());
+    assert!(counted.insert(3).is_ok());
+    assert_eq!(calls.get(), 2);
+    assert!(counted.contains(3));
+    assert_eq!(calls.get(), 4);
+
+    // Clones share the one comparator, rather than each node holding it.
+    let copy = counted.clone();
+    assert_eq!(::std::rc::Rc::strong_count(&calls), 3);
+    assert_eq!(copy.to_sorted_vec(), vec![1, 2, 3]);
+}
+
+#[test]
+fn builder() {
+    // Floats have no `Ord` of their own, so need a comparator to go in a tree,
+    // and here one that's balanced and keeps duplicates as well.
+    let by_value = |a: &f64, b: &f64| a.partial_cmp(b).unwrap();
+    let mut t = BinaryTree::from_builder(Builder::new().balanced().multiset().comparator(by_value));
+    for i in 0..100 {
+        assert!(t.insert(i as f64 / 2.0).is_ok());
+    }
+    assert!(t.insert(1.5).is_ok());
+    assert_eq!(t.len(), 101);
+    assert_eq!(t.count(1.5), 2);
+    assert!(t.height() <= 8);
+    assert!(t.validate());
+
+    let mut lazy = BinaryTree::from_builder(Builder::new().lazy().comparator(by_value));
+    for x in &[2.0, 1.0, 3.0] {
+        assert!(lazy.insert(*x).is_ok());
+    }
+    assert_eq!(lazy.remove(1.0), Ok(1.0));
+    assert_eq!(lazy.len(), 2);
+    assert_eq!(lazy.node_count(), 3);
+
+    // Without a comparator, the values' own order is used.
+    let mut plain: BinaryTree<i32> = BinaryTree::from_builder(Builder::new().multiset());
+    for x in &[2, 1, 2] {
+        assert!(plain.insert(*x).is_ok());
+    }
+    assert_eq!(plain.to_sorted_vec(), vec![1, 2, 2]);
+}
+
+#[test]
+fn remove_all_equal() {
+    // Items with no order of their own, ordered by just their group,
+    // so different ids count as equal.
+    #[derive(Clone, Copy, Debug)]
+    struct Item {
+        group: u8,
+        id: u8,
+    }
+
+    let by_group = |a: &Item, b: &Item| a.group.cmp(&b.group);
+    let mut t = BinaryTree::from_builder(Builder::new().multiset().comparator(by_group));
+    for (group, id) in &[(2, 0), (1, 1), (3, 2), (1, 3), (2, 4), (1, 5)] {
+        assert!(t.insert(Item{ group: *group, id: *id }).is_ok());
+    }
+
+    // Every copy is kept as it was inserted, so distinct items stay distinct.
+    assert_eq!(t.iter().map(|i| i.id).collect::<Vec<_>>(), vec![1, 3, 5, 0, 4, 2]);
+
+    let any_one = Item{ group: 1, id: 99 };
+    assert_eq!(t.remove_all_equal(any_one), 3);
+    assert_eq!(t.remove_all_equal(any_one), 0);
+    assert_eq!(t.iter().map(|i| i.group).collect::<Vec<_>>(), vec![2, 2, 3]);
+    assert_eq!(t.root.size, 3);
+    assert!(t.first_invariant_violation().is_none());
+
+    // Taking out the root leaves its remaining child in its place.
+    assert_eq!(t.remove_all_equal(Item{ group: 2, id: 0 }), 2);
+    assert_eq!(t.iter().map(|i| i.id).collect::<Vec<_>>(), vec![2]);
+
+    let mut s = BinaryTree::<i32>::new();
+    assert_eq!(s.remove_all_equal(1), 0);
+    assert!(s.insert(1).is_ok());
+    assert_eq!(s.remove_all_equal(1), 1);
+    assert_eq!(s.iter().count(), 0);
+}
+
+#[test]
+fn insert_locally_balanced() {
+    let mut plain = BinaryTree::<i32>::new();
+    let mut local = BinaryTree::<i32>::new();
+    for x in 0..1000 {
+        assert!(plain.insert(x).is_ok());
+        assert!(local.insert_locally_balanced(x).is_ok());
+    }
+    assert!(local.insert_locally_balanced(500).is_err());
+
+    assert_eq!(plain.height(), 999);
+    assert!(local.height() < 20);
+    assert_eq!(local.root.levels, local.height() + 1);
+    assert!(local.iter().cloned().eq(0..1000));
+    assert_eq!(local.root.size, 1000);
+    assert_eq!(local.first_invariant_violation(), None);
+
+    // Shuffled inserts only rotate where needed.
+    let mut shuffled = BinaryTree::<i32>::new();
+    for i in 0..101 {
+        assert!(shuffled.insert_locally_balanced(i * 37 % 101).is_ok());
+    }
+    assert!(shuffled.iter().cloned().eq(0..101));
+
+    // Starting from a tree already skewed by plain inserts, the insert below
+    // leaves the root at a balance factor of 3, which still gets rotated.
+    //       10              5
+    //      /               / \
+    //     5               3   10
+    //    / \      =>     /    /
+    //   3   7           1    7
+    //  /
+    // 1
+    let mut skewed = BinaryTree::<i32>::new();
+    for x in &[10, 5, 3, 7] {
+        assert!(skewed.insert(*x).is_ok());
+    }
+    assert!(skewed.insert_locally_balanced(1).is_ok());
+    assert_eq!(skewed.root.val, Some(5));
+    assert_eq!(skewed.height(), 2);
+    assert_eq!(skewed.iter_preorder().cloned().collect::<Vec<_>>(), vec![5, 3, 1, 10, 7]);
+    assert_eq!(skewed.first_invariant_violation(), None);
+}
+
+#[test]
+fn intersection_count_disjoint() {
+    let mut a = BinaryTree::<i32>::new();
+    let mut b = BinaryTree::<i32>::new();
+    for x in &[5, 1, 9, 3] {
+        assert!(a.insert(*x).is_ok());
+    }
+    for x in &[4, 8, 2, 6] {
+        assert!(b.insert(*x).is_ok());
+    }
+
+    assert_eq!(a.intersection_count(&b), 0);
+    assert_eq!(b.intersection_count(&a), 0);
+    assert_eq!(a.intersection_count(&BinaryTree::new()), 0);
+}
+
+#[test]
+fn intersection_count_overlapping() {
+    let mut a = BinaryTree::<i32>::new();
+    let mut b = BinaryTree::<i32>::new();
+    for x in &[5, 1, 9, 3, 7] {
+        assert!(a.insert(*x).is_ok());
+    }
+    for x in &[3, 8, 9, 2, 5, 10] {
+        assert!(b.insert(*x).is_ok());
+    }
+
+    assert_eq!(a.intersection_count(&b), 3);
+    assert_eq!(b.intersection_count(&a), 3);
+}
+
+#[test]
+fn intersection_count_identical() {
+    let mut a = BinaryTree::<i32>::new();
+    let mut b = BinaryTree::<i32>::new();
+    // Same values, different insertion order, so different shapes.
+    for x in &[4, 2, 6, 1, 3, 5, 7] {
+        assert!(a.insert(*x).is_ok());
+    }
+    for x in &[1, 2, 3, 4, 5, 6, 7] {
+        assert!(b.insert(*x).is_ok());
+    }
+
+    assert_eq!(a.intersection_count(&b), 7);
+    assert_eq!(a.intersection_count(&a), 7);
+}
+
+#[test]
+fn merge() {
+    let mut a: BinaryTree<i32> = vec![5, 1, 9, 3].into_iter().collect();
+    let b: BinaryTree<i32> = vec![4, 3, 10, 5, 0].into_iter().collect();
+    a.merge(b);
+    assert_eq!(a.to_sorted_vec(), vec![0, 1, 3, 4, 5, 9, 10]);
+    assert_eq!(a.len(), 7);
+
+    a.merge(BinaryTree::new());
+    assert_eq!(a.len(), 7);
+}
+
+#[test]
+fn diff() {
+    let mut a = BinaryTree::<i32>::new();
+    let mut b = BinaryTree::<i32>::new();
+    for x in &[5, 1, 9, 3, 7] {
+        assert!(a.insert(*x).is_ok());
+    }
+    for x in &[3, 8, 9, 2, 5, 10] {
+        assert!(b.insert(*x).is_ok());
+    }
+
+    assert_eq!(a.diff(&b), (vec![2, 8, 10], vec![1, 7]));
+    assert_eq!(b.diff(&a), (vec![1, 7], vec![2, 8, 10]));
+    assert_eq!(a.diff(&a), (vec![], vec![]));
+    assert_eq!(a.diff(&BinaryTree::new()), (vec![], vec![1, 3, 5, 7, 9]));
+}
+
+#[test]
+fn diff_multiset() {
+    let mut a = BinaryTree::<i32>::new_multiset();
+    let mut b = BinaryTree::<i32>::new_multiset();
+    for x in &[2, 2, 2, 4] {
+        assert!(a.insert(*x).is_ok());
+    }
+    for x in &[2, 4, 4, 6] {
+        assert!(b.insert(*x).is_ok());
+    }
+
+    assert_eq!(a.diff(&b), (vec![4, 6], vec![2, 2]));
+}
+
+#[test]
+fn merge_with() {
+    let mut t = BinaryTree::<i32>::new();
+    for x in &[5, 1, 9, 3] {
+        assert!(t.insert(*x).is_ok());
+    }
+    let other = [2, 3, 9, 10];
+
+    let merged: Vec<_> = t.merge_with(other.iter()).collect();
+    assert_eq!(merged, vec![
+        MergeSide::Tree(&1),
+        MergeSide::Other(&2),
+        MergeSide::Both(&3, &3),
+        MergeSide::Tree(&5),
+        MergeSide::Both(&9, &9),
+        MergeSide::Other(&10),
+    ]);
+
+    let empty = BinaryTree::<i32>::new();
+    assert_eq!(empty.merge_with(&other[..1]).collect::<Vec<_>>(), vec![MergeSide::Other(&2)]);
+    assert_eq!(t.merge_with(&[]).count(), 4);
+}
+
+#[test]
+fn structurally_eq() {
+    let mut a = BinaryTree::<i32>::new();
+    let mut b = BinaryTree::<i32>::new();
+    let mut c = BinaryTree::<i32>::new();
+    for x in &[4, 2, 6, 1, 3] {
+        assert!(a.insert(*x).is_ok());
+        assert!(b.insert(*x).is_ok());
+    }
+    // Same values, different shape.
+    for x in &[2, 1, 4, 3, 6] {
+        assert!(c.insert(*x).is_ok());
+    }
+
+    assert!(a.structurally_eq(&b));
+    assert!(!a.structurally_eq(&c));
+    assert!(BinaryTree::<i32>::new().structurally_eq(&BinaryTree::new()));
+    assert!(!a.structurally_eq(&BinaryTree::new()));
+}
+
+#[test]
+fn eq_by_contents() {
+    let a: BinaryTree<i32> = vec![3, 5, 1].into_iter().collect();
+    let b: BinaryTree<i32> = vec![1, 3, 5].into_iter().collect();
+    let c: BinaryTree<i32> = vec![3, 5].into_iter().collect();
+
+    // Different shapes, same values.
+    assert!(!a.structurally_eq(&b));
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert_eq!(BinaryTree::<i32>::new(), BinaryTree::new());
+
+    // Copies count in multiset mode.
+    let mut m = BinaryTree::<i32>::new_multiset();
+    for x in &[1, 3, 5, 5] {
+        assert!(m.insert(*x).is_ok());
+    }
+    assert_ne!(a, m);
+    assert_eq!(m.remove(5), Ok(5));
+    assert_eq!(a, m);
+}
+
+#[test]
+fn default() {
+    #[derive(Default)]
+    struct Holder {
+        tree: BinaryTree<i32>,
+    }
+
+    let mut h = Holder::default();
+    assert_eq!(h.tree.len(), 0);
+    assert!(h.tree.insert(1).is_ok());
+    assert_eq!(h.tree, vec![1].into_iter().collect());
+}
+
+#[test]
+fn clone() {
+    let mut t = BinaryTree::<i32>::new();
+    for x in &[5, 3, 8, 1] {
+        assert!(t.insert(*x).is_ok());
+    }
+    let snapshot = t.clone();
+    assert!(snapshot.structurally_eq(&t));
+
+    assert_eq!(t.remove(5), Ok(5));
+    assert!(t.insert(9).is_ok());
+    assert!(t.insert(2).is_ok());
+
+    // The clone keeps its own nodes.
+    assert_eq!(snapshot.to_sorted_vec(), vec![1, 3, 5, 8]);
+    assert_eq!(snapshot.len(), 4);
+    assert_eq!(t.to_sorted_vec(), vec![1, 2, 3, 8, 9]);
+}
+
+#[test]
+fn is_mirror_of() {
+    let reversed = |values: &[i32]| {
+        let mut t = BinaryTree::with_comparator(|a: &i32, b: &i32| b.cmp(a));
+        for x in values {
+            assert!(t.insert(*x).is_ok());
+        }
+        t
+    };
+
+    let a = sample_tree();
+    let m = reversed(&[5, 3, 8, 1, 4, 9, 2]);
+    assert!(a.is_mirror_of(&m));
+    assert!(m.is_mirror_of(&a));
+    assert!(!a.is_mirror_of(&a));
+    assert_eq!(m.left_spine(), vec![5, 8, 9]);
+
+    // Same values in a different shape.
+    assert!(!a.is_mirror_of(&reversed(&[4, 2, 8, 1, 3, 5, 9])));
+    assert!(!a.is_mirror_of(&tree_of(&[5, 8, 3])));
+
+    let one = tree_of(&[7]);
+    assert!(one.is_mirror_of(&one));
+    assert!(BinaryTree::<i32>::new().is_mirror_of(&BinaryTree::new()));
+}
+
+#[test]
+fn structurally_eq_deep() {
+    // On a thread with a small stack, which a recursive comparison would overflow.
+    let comparer = ::std::thread::Builder::new().stack_size(64 * 1024).spawn(|| {
+        let a = left_chain(100_000);
+        let mut b = left_chain(100_000);
+        assert!(a.structurally_eq(&b));
+        // The chain's bookkeeping matches what inserting would have given.
+        assert_eq!(a.len(), 100_000);
+        assert_eq!(a.height(), 99_999);
+        assert_eq!(a.root.left.as_ref().unwrap().size, 99_999);
+
+        // Change only the deepest node.
+        let mut deepest = &mut b.root;
+        while deepest.left.is_some() {
+            deepest = deepest.left.as_mut().unwrap();
+        }
+        deepest.val = Some(-1);
+        assert!(!a.structurally_eq(&b));
+    }).unwrap();
+    assert!(comparer.join().is_ok());
+}
+
+#[test]
+fn leaves() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.leaves().count(), 0);
+
+    assert!(t.insert(5).is_ok());
+    assert_eq!(t.leaves().cloned().collect::<Vec<_>>(), vec![5]);
+
+    let leaves = |t: &BinaryTree<i32>| t.leaves().cloned().collect::<Vec<_>>();
+    assert_eq!(leaves(&sample_tree()), vec![2, 4, 9]);
+
+    // A perfect tree has its whole bottom level as leaves, a zigzag just one.
+    assert_eq!(leaves(&tree_of(&[4, 2, 6, 1, 3, 5, 7])), vec![1, 3, 5, 7]);
+    assert_eq!(leaves(&tree_of(&[1, 5, 2, 4, 3])), vec![3]);
+
+    // With 9 removed, 8 has nothing left below it.
+    let mut lazy = lazy_sample_tree();
+    assert_eq!(leaves(&lazy), vec![2, 4, 8]);
+    for x in &[1, 2, 4, 5, 8] {
+        assert_eq!(lazy.remove(*x), Ok(*x));
+    }
+    assert_eq!(lazy.leaves().count(), 0);
+}
+
+#[test]
+fn min_max() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.min(), None);
+    assert_eq!(t.max(), None);
+
+    assert!(t.insert(5).is_ok());
+    assert_eq!(t.min(), Some(5));
+    assert_eq!(t.max(), Some(5));
+
+    let mut t = sample_tree();
+    assert_eq!(t.min(), Some(1));
+    assert_eq!(t.max(), Some(9));
+
+    // The minimum's right child takes over.
+    assert_eq!(t.remove(1), Ok(1));
+    assert_eq!(t.min(), Some(2));
+
+    let mut lazy = BinaryTree::<i32>::new_lazy();
+    for x in &[5, 3, 8] {
+        assert!(lazy.insert(*x).is_ok());
+    }
+    assert_eq!(lazy.remove(3), Ok(3));
+    assert_eq!(lazy.remove(8), Ok(8));
+    assert_eq!(lazy.min(), Some(5));
+    assert_eq!(lazy.max(), Some(5));
+}
+
+#[test]
+fn remove_min_max() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.remove_min(), None);
+    assert_eq!(t.remove_max(), None);
+
+    // The minimum 1 has a right child, which must be relinked in its place.
+    for x in &[6, 1, 9, 4, 2, 5, 8, 10, 7] {
+        assert!(t.insert(*x).is_ok());
+    }
+    let mut popped = Vec::new();
+    while let Some(x) = t.remove_min() {
+        popped.push(x);
+        assert!(t.validate());
+    }
+    assert_eq!(popped, vec![1, 2, 4, 5, 6, 7, 8, 9, 10]);
+    assert!(t.is_empty());
+
+    let mut m = BinaryTree::<i32>::new_multiset();
+    for x in &[3, 8, 8, 1] {
+        assert!(m.insert(*x).is_ok());
+    }
+    assert_eq!(m.remove_max(), Some(8));
+    assert_eq!(m.remove_max(), Some(8));
+    assert_eq!(m.remove_max(), Some(3));
+    assert_eq!(m.to_sorted_vec(), vec![1]);
+}
+
+#[test]
+fn rank() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.rank(5), 0);
+
+    for x in &[50, 20, 80, 10, 30, 70, 90] {
+        assert!(t.insert(*x).is_ok());
+    }
+    // Present values, including the minimum and maximum.
+    assert_eq!(t.rank(10), 0);
+    assert_eq!(t.rank(50), 3);
+    assert_eq!(t.rank(90), 6);
+    // Absent values, between and beyond those present.
+    assert_eq!(t.rank(25), 2);
+    assert_eq!(t.rank(0), 0);
+    assert_eq!(t.rank(100), 7);
+
+    for x in &[10, 30, 50, 90] {
+        assert_eq!(t.kth_smallest(t.rank(*x)), Some(*x));
+    }
+}
+
+#[test]
+fn kth_smallest() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.kth_smallest(0), None);
+
+    for x in &[50, 20, 80, 10, 30, 70, 90, 60] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.kth_smallest(0), Some(10));
+    assert_eq!(t.kth_smallest(2), Some(30));
+    assert_eq!(t.kth_smallest(3), Some(50));
+    assert_eq!(t.kth_smallest(4), Some(60));
+    assert_eq!(t.kth_smallest(7), Some(90));
+    assert_eq!(t.kth_smallest(8), None);
+
+    // Ranks shift once a value is gone.
+    assert_eq!(t.remove(20), Ok(20));
+    assert_eq!(t.kth_smallest(1), Some(30));
+    assert_eq!(t.kth_smallest(7), None);
+}
+
+#[test]
+fn spines() {
+    let t = BinaryTree::<i32>::new();
+    assert_eq!(t.left_spine(), Vec::<i32>::new());
+    assert_eq!(t.right_spine(), Vec::<i32>::new());
+
+    let t = sample_tree();
+    assert_eq!(t.left_spine(), vec![5, 3, 1]);
+    assert_eq!(t.right_spine(), vec![5, 8, 9]);
+
+    let skewed = tree_of(&[4, 3, 2, 1]);
+    assert_eq!(skewed.left_spine(), vec![4, 3, 2, 1]);
+    assert_eq!(skewed.right_spine(), vec![4]);
+
+    // The spines stop at the first missing child, however deep the tree goes.
+    let zigzag = tree_of(&[1, 5, 2, 4, 3]);
+    assert_eq!(zigzag.left_spine(), vec![1]);
+    assert_eq!(zigzag.right_spine(), vec![1, 5]);
+
+    let lazy = lazy_sample_tree();
+    assert_eq!(lazy.left_spine(), vec![5, 1]);
+    assert_eq!(lazy.right_spine(), vec![5, 8]);
+}
+
+#[test]
+fn root_to_leaf_paths() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.root_to_leaf_paths(), Vec::<Vec<i32>>::new());
+
+    assert!(t.insert(5).is_ok());
+    assert_eq!(t.root_to_leaf_paths(), vec![vec![5]]);
+
+    assert_eq!(sample_tree().root_to_leaf_paths(),
+               vec![vec![5, 3, 1, 2], vec![5, 3, 4], vec![5, 8, 9]]);
+    assert_eq!(tree_of(&[4, 2, 6, 1, 3, 5, 7]).root_to_leaf_paths(),
+               vec![vec![4, 2, 1], vec![4, 2, 3], vec![4, 6, 5], vec![4, 6, 7]]);
+    assert_eq!(tree_of(&[1, 5, 2, 4, 3]).root_to_leaf_paths(), vec![vec![1, 5, 2, 4, 3]]);
+
+    assert_eq!(lazy_sample_tree().root_to_leaf_paths(),
+               vec![vec![5, 1, 2], vec![5, 4], vec![5, 8]]);
+}
+
+#[test]
+fn edges() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.edges().count(), 0);
+    assert!(t.insert(5).is_ok());
+    assert_eq!(t.edges().count(), 0);
+
+    let edges = |t: &BinaryTree<i32>| t.edges().map(|(p, c)| (*p, *c)).collect::<Vec<_>>();
+    assert_eq!(edges(&sample_tree()), vec![(5, 3), (3, 1), (1, 2), (3, 4), (5, 8), (8, 9)]);
+    assert_eq!(edges(&tree_of(&[1, 5, 2, 4, 3])), vec![(1, 5), (5, 2), (2, 4), (4, 3)]);
+
+    // The values below the removed 3 link up to 5.
+    assert_eq!(edges(&lazy_sample_tree()), vec![(5, 1), (1, 2), (5, 4), (5, 8)]);
+}
+
+#[test]
+fn iter_preorder_postorder() {
+    let t = BinaryTree::<i32>::new();
+    assert_eq!(t.iter_preorder().count(), 0);
+    assert_eq!(t.iter_postorder().count(), 0);
+
+    let t = sample_tree();
+    assert_eq!(t.iter_preorder().cloned().collect::<Vec<_>>(), vec![5, 3, 1, 2, 4, 8, 9]);
+    assert_eq!(t.iter_postorder().cloned().collect::<Vec<_>>(), vec![2, 1, 4, 3, 9, 8, 5]);
+
+    let perfect = tree_of(&[4, 2, 6, 1, 3, 5, 7]);
+    assert_eq!(perfect.iter_preorder().cloned().collect::<Vec<_>>(), vec![4, 2, 1, 3, 6, 5, 7]);
+    assert_eq!(perfect.iter_postorder().cloned().collect::<Vec<_>>(), vec![1, 3, 2, 5, 7, 6, 4]);
+}
+
+#[test]
+fn range() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.range(1, 10).count(), 0);
+
+    // Shuffled, so the range crosses several subtrees.
+    for x in &[11, 4, 16, 2, 8, 13, 19, 1, 3, 6, 10, 12, 15, 18, 20, 5, 7, 9, 14, 17] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert!(t.range(5, 10).cloned().eq(5..=10));
+    assert!(t.range(7, 7).cloned().eq(7..=7));
+    assert_eq!(t.range(10, 5).count(), 0);
+
+    // Bounds beyond the smallest and largest values.
+    assert!(t.range(-5, 3).cloned().eq(1..=3));
+    assert!(t.range(18, 100).cloned().eq(18..=20));
+    assert!(t.range(i32::MIN, i32::MAX).cloned().eq(1..=20));
+    assert_eq!(t.range(21, 30).count(), 0);
+}
+
+#[test]
+fn iter_levelorder() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.iter_levelorder().count(), 0);
+
+    //          5
+    //     3        8
+    //   1   4    7   9
+    //    2      6
+    for x in &[5, 3, 8, 1, 4, 7, 9, 2, 6] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.iter_levelorder().cloned().collect::<Vec<_>>(), vec![5, 3, 8, 1, 4, 7, 9, 2, 6]);
+}
+
+#[test]
+fn checked_sum() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.checked_sum(), Some(0));
+    for x in &[3, -5, 10] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.checked_sum(), Some(8));
+
+    assert!(t.insert(i32::MAX - 5).is_ok());
+    assert_eq!(t.checked_sum(), None);
+}
+
+#[test]
+fn saturating_sum() {
+    let mut t = BinaryTree::<i32>::new();
+    for x in &[1, 2, 3] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.saturating_sum(), 6);
+
+    assert!(t.insert(i32::MAX - 1).is_ok());
+    assert!(t.insert(i32::MAX).is_ok());
+    assert_eq!(t.saturating_sum(), i32::MAX);
+
+    let mut n = BinaryTree::<i32>::new();
+    for x in &[i32::MIN, i32::MIN + 1, -1] {
+        assert!(n.insert(*x).is_ok());
+    }
+    assert_eq!(n.checked_sum(), None);
+    assert_eq!(n.saturating_sum(), i32::MIN);
+}
+
+#[test]
+fn between() {
+    let mut t = BinaryTree::<i32>::new();
+    assert!(t.between(0, 10).is_empty());
+
+    for x in &[5, 2, 8, 1, 3, 7, 9, 4, 6] {
+        assert!(t.insert(*x).is_ok());
+    }
+
+    // Stored bounds are excluded.
+    assert_eq!(t.between(3, 7), vec![4, 5, 6]);
+    assert_eq!(t.between(2, 3), Vec::<i32>::new());
+    assert_eq!(t.between(7, 3), Vec::<i32>::new());
+
+    // Bounds not in the tree, or outside it.
+    assert_eq!(t.between(0, 3), vec![1, 2]);
+    assert_eq!(t.between(-10, 100), vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+}
+
+#[test]
+fn between_inclusive() {
+    let mut t = BinaryTree::<i32>::new();
+    for x in &[5, 2, 8, 1, 3, 7, 9, 4, 6] {
+        assert!(t.insert(*x).is_ok());
+    }
+
+    assert_eq!(t.between_inclusive(3, 7), vec![3, 4, 5, 6, 7]);
+    assert_eq!(t.between_inclusive(3, 3), vec![3]);
+    assert_eq!(t.between_inclusive(7, 3), Vec::<i32>::new());
+    assert_eq!(t.between_inclusive(8, 100), vec![8, 9]);
+}
+
+#[test]
+fn first_invariant_violation() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.first_invariant_violation(), None);
+
+    for x in &[5, 3, 8, 1, 4, 9] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.first_invariant_violation(), None);
+
+    // Corrupt the tree so 6 sits in the left subtree of 5.
+    t.root.left.as_mut().unwrap().right.as_mut().unwrap().val = Some(6);
+    assert_eq!(t.first_invariant_violation(), Some((6, 5)));
+
+    // Only the first violation is reported.
+    t.root.right.as_mut().unwrap().right.as_mut().unwrap().val = Some(7);
+    assert_eq!(t.first_invariant_violation(), Some((6, 5)));
+    t.root.left.as_mut().unwrap().right.as_mut().unwrap().val = Some(4);
+    assert_eq!(t.first_invariant_violation(), Some((8, 7)));
+}
+
+#[test]
+fn validate() {
+    let mut t = BinaryTree::<i32>::new();
+    assert!(t.validate());
+
+    for x in &[50, 30, 70, 20, 40, 60, 80, 35, 45, 65] {
+        assert!(t.insert(*x).is_ok());
+        assert!(t.validate());
+    }
+
+    // Plenty of these have two children, including the root.
+    for x in &[50, 30, 70, 45, 20, 65, 35, 40, 60, 80] {
+        assert!(t.remove(*x).is_ok());
+        assert!(t.validate());
+    }
+
+    for x in &[5, 3, 8] {
+        assert!(t.insert(*x).is_ok());
+    }
+    t.root.left.as_mut().unwrap().val = Some(6);
+    assert!(!t.validate());
+}
+
+#[test]
+fn pretty_truncated() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.pretty_truncated(10), "<empty>\n");
+
+    for x in &[5, 3, 8, 1, 4, 9] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.pretty_truncated(10), "5\n3 8\n1 4 9\n");
+    assert_eq!(t.pretty_truncated(4), "5\n3 8\n1\n... 2 more\n");
+    assert_eq!(t.pretty_truncated(0), "... 6 more\n");
+
+    let lazy = lazy_sample_tree();
+    assert_eq!(lazy.pretty_truncated(10), "5\n8\n1 4\n2\n");
+    assert_eq!(lazy.pretty_truncated(3), "5\n8\n1\n... 2 more\n");
+}
+
+#[test]
+fn pretty_truncated_large() {
+    let mut t = BinaryTree::<i32>::new();
+    for x in 0..1000 {
+        assert!(t.insert((x * 37) % 1000).is_ok());
+    }
+
+    let out = t.pretty_truncated(50);
+    let values = out.lines()
+        .take_while(|l| !l.starts_with("..."))
+        .map(|l| l.split(' ').count())
+        .sum::<usize>();
+    assert_eq!(values, 50);
+    assert!(out.ends_with("\n... 950 more\n"));
+}
+
+#[test]
+fn sibling_pairs() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.sibling_pairs().count(), 0);
+
+    //         5
+    //     3       8
+    //   1   4       9
+    //  0 2
+    for x in &[5, 3, 8, 1, 4, 9, 0, 2] {
+        assert!(t.insert(*x).is_ok());
+    }
+
+    let pairs: Vec<_> = t.sibling_pairs().map(|(l, r)| (*l, *r)).collect();
+    assert_eq!(pairs, vec![(3, 8), (1, 4), (0, 2)]);
+}
+
+#[test]
+fn height() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.height(), 0);
+    assert!(t.insert(5).is_ok());
+    assert_eq!(t.height(), 0);
+
+    let mut t = sample_tree();
+    assert_eq!(t.height(), 3);
+
+    // Removing the deepest node brings it back down.
+    assert_eq!(t.remove(2), Ok(2));
+    assert_eq!(t.height(), 2);
+    assert_eq!(t.remove(5), Ok(5));
+    assert_eq!(t.height(), 2);
+
+    let mut left = BinaryTree::<i32>::new();
+    let mut right = BinaryTree::<i32>::new();
+    for x in 0..10 {
+        assert!(left.insert(9 - x).is_ok());
+        assert!(right.insert(x).is_ok());
+    }
+    assert_eq!(left.height(), 9);
+    assert_eq!(right.height(), 9);
+
+    let values: Vec<i32> = (0..15).collect();
+    assert_eq!(BinaryTree::from_sorted_slice(&values).height(), 3);
+}
+
+#[test]
+fn checked_height() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.checked_height(0), Ok(0));
+
+    for x in 0..20 {
+        assert!(t.insert(x).is_ok());
+    }
+    assert_eq!(t.checked_height(19), Ok(19));
+    assert_eq!(t.checked_height(100), Ok(19));
+    assert_eq!(t.checked_height(18), Err(DepthOverflow{ limit: 18 }));
+    assert_eq!(t.checked_height(5), Err(DepthOverflow{ limit: 5 }));
+}
+
+#[test]
+fn from_sorted_slice() {
+    let values: Vec<i32> = (1..=15).collect();
+    let t = BinaryTree::from_sorted_slice(&values);
+
+    // 15 values fill 4 levels exactly, and height counts the edges between them.
+    assert_eq!(t.height(), 3);
+    assert_eq!(t.len(), 15);
+    assert_eq!(t.root.val, Some(8));
+    assert!(t.iter().cloned().eq(1..=15));
+
+    assert!(BinaryTree::<i32>::from_sorted_slice(&[]).is_empty());
+}
+
+#[test]
+fn try_from_sorted_slice() {
+    let t = BinaryTree::try_from_sorted_slice(&[1, 3, 5, 7, 9]).unwrap();
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(), vec![1, 3, 5, 7, 9]);
+    assert_eq!(t.height(), 2);
+    assert!(BinaryTree::<i32>::try_from_sorted_slice(&[]).is_ok());
+
+    let err = BinaryTree::try_from_sorted_slice(&[1, 3, 2, 5, 4]).unwrap_err();
+    assert_eq!(err, SortError::OutOfOrder(2));
+    assert_eq!(err.to_string(), "value at index 2 is out of order");
+
+    assert_eq!(BinaryTree::try_from_sorted_slice(&[1, 2, 2, 3]).unwrap_err(), SortError::Duplicate(2));
+}
+
+#[test]
+fn values_at_depth() {
+    let t = BinaryTree::<i32>::new();
+    assert_eq!(t.values_at_depth(0), Vec::<i32>::new());
+
+    let t = sample_tree();
+    assert_eq!(t.values_at_depth(0), vec![5]);
+    assert_eq!(t.values_at_depth(1), vec![3, 8]);
+    assert_eq!(t.values_at_depth(2), vec![1, 4, 9]);
+    assert_eq!(t.values_at_depth(3), vec![2]);
+    assert_eq!(t.values_at_depth(4), Vec::<i32>::new());
+
+    // A zigzag has a single value on each level.
+    let zigzag = tree_of(&[1, 5, 2, 4, 3]);
+    let levels: Vec<_> = (0..6).map(|d| zigzag.values_at_depth(d)).collect();
+    assert_eq!(levels, vec![vec![1], vec![5], vec![2], vec![4], vec![3], vec![]]);
+
+    let lazy = lazy_sample_tree();
+    let levels: Vec<_> = (0..4).map(|d| lazy.values_at_depth(d)).collect();
+    assert_eq!(levels, vec![vec![5], vec![8], vec![1, 4], vec![2]]);
+}
+
+#[test]
+fn internal_path_length() {
+    assert_eq!(BinaryTree::<i32>::new().internal_path_length(), 0);
+
+    // Perfect tree of 15: 1*0 + 2*1 + 4*2 + 8*3.
+    let values: Vec<i32> = (0..15).collect();
+    let balanced = BinaryTree::from_sorted_slice(&values);
+    assert_eq!(balanced.internal_path_length(), 34);
+
+    // Chain of 15: 0 + 1 + ... + 14.
+    let mut skewed = BinaryTree::<i32>::new();
+    for x in &values {
+        assert!(skewed.insert(*x).is_ok());
+    }
+    assert_eq!(skewed.internal_path_length(), 105);
+}
+
+#[test]
+fn contains_batch_sorted() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.contains_batch_sorted(&[1, 2]), vec![false, false]);
+
+    for x in &[5, 3, 8, 1, 4, 9] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.contains_batch_sorted(&[]), Vec::<bool>::new());
+    assert_eq!(
+        t.contains_batch_sorted(&[0, 1, 2, 4, 4, 5, 6, 9, 10]),
+        vec![false, true, false, true, true, true, false, true, false]);
+}
+
+#[test]
+fn remap_keys() {
     let mut t = BinaryTree::<i32>::new();
-    // Set the tree up how we want.
-    assert!(t.insert(3).is_ok());
-    assert!(t.insert(5).is_ok());
+    assert_eq!(t.remap_keys(|x| x + 1), 0);
 
-    // Check it's how we expect.
-    assert_eq!(t.val.unwrap(), 3);
-    assert_eq!(t.right.as_ref().unwrap().val.unwrap(), 5);
+    for x in &[5, 3, 8, 1, 4, -4, 9] {
+        assert!(t.insert(*x).is_ok());
+    }
 
-    // Remove the leaf node.
-    assert!(t.remove(5).is_ok());
+    // Squaring reorders the negatives, and -4 collides with 4.
+    assert_eq!(t.remap_keys(|x| x * x), 1);
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(), vec![1, 9, 16, 25, 64, 81]);
+    assert_eq!(t.first_invariant_violation(), None);
 
-    // Check the root no longer points anywhere.
-    assert!(t.left.is_none());
-    assert!(t.right.is_none());
+    // Reversing the order with no collisions.
+    assert_eq!(t.remap_keys(|x| -x), 0);
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(), vec![-81, -64, -25, -16, -9, -1]);
+}
 
-    // Remove the root.
-    assert!(t.remove(3).is_ok());
+#[test]
+fn rebalance_subtree() {
+    let mut t = BinaryTree::<i32>::new();
+    let mut expected = BinaryTree::<i32>::new();
+    // A small left side, and a right side skewed into a chain under 10.
+    for x in &[5, 2, 1, 3, 10, 11, 12, 13, 14, 15, 16] {
+        assert!(t.insert(*x).is_ok());
+    }
+    for x in &[5, 2, 1, 3, 13, 11, 10, 12, 15, 14, 16] {
+        assert!(expected.insert(*x).is_ok());
+    }
+    assert_eq!(t.height(), 7);
 
-    // Check it's now empty.
-    assert!(t.val.is_none());
+    assert!(!t.rebalance_subtree(4));
+    assert!(t.rebalance_subtree(10));
+    assert!(t.structurally_eq(&expected));
+    assert_eq!(t.height(), 3);
+    assert_eq!(t.iter().count(), 11);
+
+    let mut e = BinaryTree::<i32>::new();
+    assert!(!e.rebalance_subtree(1));
 }
 
 #[test]
-fn remove_left_child() {
+fn fill_ratio() {
     let mut t = BinaryTree::<i32>::new();
-    // Set the tree up how we want.
-    assert!(t.insert(3).is_ok());
-    assert!(t.insert(1).is_ok());
+    assert_eq!(t.fill_ratio(), 0.0);
 
-    // Check it's how we expect.
-    assert_eq!(t.val.unwrap(), 3);
-    assert_eq!(t.left.as_ref().unwrap().val.unwrap(), 1);
+    assert!(t.insert(4).is_ok());
+    assert!((t.fill_ratio() - 1.0).abs() < 1e-9);
 
-    // Remove the root node.
-    assert!(t.remove(3).is_ok());
+    for x in &[2, 6, 1, 3, 5, 7] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert!((t.fill_ratio() - 1.0).abs() < 1e-9);
 
-    // Check the root now contains 5, and nothing else.
-    assert_eq!(t.val.unwrap(), 1);
-    assert!(t.left.is_none());
-    assert!(t.right.is_none());
+    // A skewed tree of 10 nodes and height 9.
+    let mut s = BinaryTree::<i32>::new();
+    for x in 0..10 {
+        assert!(s.insert(x).is_ok());
+    }
+    assert!((s.fill_ratio() - 10.0 / 1023.0).abs() < 1e-9);
 }
 
 #[test]
-fn remove_right_child() {
+fn multiset_insert_remove() {
+    let mut t = BinaryTree::<i32>::new_multiset();
+    for x in &[5, 3, 5, 8, 3, 5] {
+        assert_eq!(t.insert(*x), Ok(()));
+    }
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(), vec![3, 3, 5, 5, 5, 8]);
+
+    // Copies come off one at a time.
+    assert_eq!(t.remove(5), Ok(5));
+    assert_eq!(t.remove(3), Ok(3));
+    assert_eq!(t.remove(3), Ok(3));
+    assert_eq!(t.remove(3), Err(3));
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(), vec![5, 5, 8]);
+    assert_eq!(t.first_invariant_violation(), None);
+}
+
+#[test]
+fn count() {
+    let mut t = BinaryTree::<i32>::new_multiset();
+    assert_eq!(t.count(7), 0);
+
+    for x in &[7, 2, 7, 7, 9] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.count(7), 3);
+    assert_eq!(t.count(2), 1);
+    assert_eq!(t.count(4), 0);
+
+    // The node only goes once its last copy does.
+    assert_eq!(t.remove(7), Ok(7));
+    assert_eq!(t.remove(7), Ok(7));
+    assert_eq!(t.count(7), 1);
+    assert_eq!(t.node_count(), 3);
+    assert_eq!(t.remove(7), Ok(7));
+    assert_eq!(t.count(7), 0);
+    assert_eq!(t.node_count(), 2);
+    assert_eq!(t.remove(7), Err(7));
+
+    let mut set = BinaryTree::<i32>::new();
+    assert!(set.insert(7).is_ok());
+    assert!(set.insert(7).is_err());
+    assert_eq!(set.count(7), 1);
+}
+
+#[test]
+fn mode() {
+    let mut t = BinaryTree::<i32>::new_multiset();
+    assert_eq!(t.mode(), None);
+
+    for x in &[5, 3, 8, 3, 9, 3, 8, 1] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.mode(), Some(3));
+
+    // 8 catches up with 3, and the smaller wins the tie.
+    for x in &[8, 9, 9] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.mode(), Some(3));
+    assert_eq!(t.remove(3), Ok(3));
+    assert_eq!(t.mode(), Some(8));
+}
+
+#[test]
+fn iter() {
     let mut t = BinaryTree::<i32>::new();
-    // Set the tree up how we want.
-    assert!(t.insert(3).is_ok());
-    assert!(t.insert(5).is_ok());
+    assert_eq!(t.iter().next(), None);
 
-    // Check it's how we expect.
-    assert_eq!(t.val.unwrap(), 3);
-    assert_eq!(t.right.as_ref().unwrap().val.unwrap(), 5);
+    // 0..101 in a scrambled order.
+    for i in 0..101 {
+        assert!(t.insert(i * 37 % 101).is_ok());
+    }
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(), (0..101).collect::<Vec<_>>());
+}
 
-    // Remove the root node.
-    assert!(t.remove(3).is_ok());
+#[test]
+fn into_iter() {
+    let t = BinaryTree::<i32>::new();
+    assert_eq!(t.into_iter().next(), None);
 
-    // Check the root now contains 5, and nothing else.
-    assert_eq!(t.val.unwrap(), 5);
-    assert!(t.left.is_none());
-    assert!(t.right.is_none());
+    // Scrambled values from a simple linear congruential generator.
+    let mut t = BinaryTree::<i32>::new_multiset();
+    let mut x: u32 = 12345;
+    for _ in 0..500 {
+        x = x.wrapping_mul(1103515245).wrapping_add(12345);
+        assert!(t.insert((x >> 16) as i32 % 200).is_ok());
+    }
+
+    let mut values = Vec::new();
+    for v in t {
+        values.push(v);
+    }
+    assert_eq!(values.len(), 500);
+    assert!(values.windows(2).all(|w| w[0] <= w[1]));
 }
 
 #[test]
-fn remove_both_children() {
+fn iter_in_place() {
+    let build = |values: &[i32]| {
+        let mut t = BinaryTree::<i32>::new_multiset();
+        for x in values {
+            assert!(t.insert(*x).is_ok());
+        }
+        t
+    };
+    let values = [5, 3, 8, 1, 4, 9, 2, 7, 6, 4, 10];
+
+    let mut t = build(&values);
+    let sorted: Vec<i32> = t.iter().cloned().collect();
+    assert_eq!(t.iter_in_place().collect::<Vec<_>>(), sorted);
+    assert!(t.structurally_eq(&build(&values)));
+
+    // Stopping early still restores every link.
+    assert_eq!(t.iter_in_place().take(4).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    assert!(t.structurally_eq(&build(&values)));
+    assert_eq!(t.len(), 11);
+
+    let mut deep = BinaryTree::<i32>::new();
+    for x in 0..DEEP {
+        assert!(deep.insert(x).is_ok());
+    }
+    assert!(deep.iter_in_place().eq(0..DEEP));
+    assert_eq!(deep.right_spine().len(), DEEP as usize);
+
+    let mut e = BinaryTree::<i32>::new();
+    assert_eq!(e.iter_in_place().next(), None);
+    assert!(e.insert(1).is_ok());
+    assert_eq!(e.iter_in_place().collect::<Vec<_>>(), vec![1]);
+}
+
+#[test]
+fn iter_deep() {
     let mut t = BinaryTree::<i32>::new();
-    // Set the tree up how we want.
-    assert!(t.insert(3).is_ok());
-    assert!(t.insert(5).is_ok());
-    assert!(t.insert(1).is_ok());
+    for x in (0..DEEP).rev() {
+        assert!(t.insert(x).is_ok());
+    }
+    assert!(t.iter().cloned().eq(0..DEEP));
+}
 
-    // Check it's how we expect.
-    assert_eq!(t.val.unwrap(), 3);
-    assert_eq!(t.left.as_ref().unwrap().val.unwrap(), 1);
-    assert_eq!(t.right.as_ref().unwrap().val.unwrap(), 5);
+#[test]
+fn drop_deep() {
+    // Far deeper than insert could build without overflowing, so the chain
+    // is put together by hand, and dropped on a thread with a small stack.
+    let dropper = ::std::thread::Builder::new().stack_size(64 * 1024).spawn(|| {
+        drop(left_chain(100_000));
+    }).unwrap();
+    assert!(dropper.join().is_ok());
 
-    // Remove the root node.
-    assert!(t.remove(3).is_ok());
+    // Both sides of every node are freed, not just the left.
+    let counted: Vec<_> = (0..100).map(::std::rc::Rc::new).collect();
+    let t = BinaryTree::from_sorted_slice(&counted);
+    assert!(counted.iter().all(|v| ::std::rc::Rc::strong_count(v) == 2));
+    drop(t);
+    assert!(counted.iter().all(|v| ::std::rc::Rc::strong_count(v) == 1));
+}
 
-    // Check the tree is now how we expect.
-    assert_eq!(t.val.unwrap(), 1);
-    assert_eq!(t.right.as_ref().unwrap().val.unwrap(), 5);
-    assert!(t.left.is_none());
+#[test]
+fn iter_groups() {
+    let mut t = BinaryTree::<i32>::new_multiset();
+    assert_eq!(t.iter_groups().count(), 0);
+
+    for x in &[4, 2, 4, 9, 2, 4, 1] {
+        assert!(t.insert(*x).is_ok());
+    }
+    let groups: Vec<_> = t.iter_groups().map(|(v, n)| (*v, n)).collect();
+    assert_eq!(groups, vec![(1, 1), (2, 2), (4, 3), (9, 1)]);
+
+    // Outside multiset mode every group has a single copy.
+    let mut s = BinaryTree::<i32>::new();
+    for x in &[4, 2, 4] {
+        let _ = s.insert(*x);
+    }
+    let groups: Vec<_> = s.iter_groups().map(|(v, n)| (*v, n)).collect();
+    assert_eq!(groups, vec![(2, 1), (4, 1)]);
 }
 
 #[test]
-fn remove_nonexistent() {
+fn display() {
     let mut t = BinaryTree::<i32>::new();
-    assert!(t.remove(14).is_err());
-    assert!(t.insert(3).is_ok());
-    assert!(t.insert(5).is_ok());
-    assert!(t.insert(1).is_ok());
-    assert!(t.remove(14).is_err());
-    assert!(t.remove(0).is_err());
-    assert!(t.insert(14).is_ok());
-    assert!(t.remove(14).is_ok());
+    assert_eq!(t.to_string(), "<empty>\n");
+
+    for x in &[5, 3, 8, 1, 4, 9] {
+        assert!(t.insert(*x).is_ok());
+    }
+    let expected = [
+        "        9",
+        "    8",
+        "5",
+        "        4",
+        "    3",
+        "        1",
+    ];
+    assert_eq!(t.to_string(), expected.join("\n") + "\n");
+
+    // The removed 3 and 9 are left out, and 1 and 4 stay at the depth below 3.
+    let mut lazy = lazy_sample_tree();
+    let expected = [
+        "    8",
+        "5",
+        "        4",
+        "            2",
+        "        1",
+    ];
+    assert_eq!(lazy.to_string(), expected.join("\n") + "\n");
+    for x in &[1, 2, 4, 5, 8] {
+        assert_eq!(lazy.remove(*x), Ok(*x));
+    }
+    assert_eq!(lazy.to_string(), "<empty>\n");
 }
 
 #[test]
-fn remove_left() {
+fn serialize_compact() {
     let mut t = BinaryTree::<i32>::new();
-    // Set the tree up how we want.
-    assert!(t.insert(3).is_ok());
-    assert!(t.insert(5).is_ok());
-    assert!(t.insert(1).is_ok());
+    assert_eq!(t.serialize_compact(), "#");
 
-    assert!(t.remove(1).is_ok());
+    for x in &[5, 3, 8, 1] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.serialize_compact(), "5(3(1,#),8)");
+
+    assert!(t.insert(-2).is_ok());
+    assert!(t.insert(9).is_ok());
+    assert_eq!(t.serialize_compact(), "5(3(1(-2,#),#),8(#,9))");
+
+    // Removed values aren't brought back to life by a round trip. The tree is
+    // written as `compact` would leave it.
+    let mut lazy = lazy_sample_tree();
+    let s = lazy.serialize_compact();
+    assert_eq!(s, "4(2(1,#),8(5,#))");
+    let back = BinaryTree::<i32>::deserialize_compact(&s).unwrap();
+    assert_eq!(back.to_sorted_vec(), vec![1, 2, 4, 5, 8]);
+    lazy.compact();
+    assert!(back.structurally_eq(&lazy));
+    assert_eq!(lazy.serialize_compact(), s);
+
+    let mut gone = BinaryTree::<i32>::new_lazy();
+    assert!(gone.insert(1).is_ok());
+    assert_eq!(gone.remove(1), Ok(1));
+    assert_eq!(gone.serialize_compact(), "#");
 }
 
 #[test]
-fn remove_recursive_collapse() {
+fn deserialize_compact_round_trip() {
+    let mut trees = vec![BinaryTree::<i32>::new()];
+    for values in &[vec![7], vec![5, 3, 8, 1, 4, 9], vec![1, 2, 3, 4], vec![4, 3, 2, 1, 10, 6, 5]] {
+        let mut t = BinaryTree::new();
+        for x in values {
+            assert!(t.insert(*x).is_ok());
+        }
+        trees.push(t);
+    }
+
+    for t in &trees {
+        let s = t.serialize_compact();
+        let u = BinaryTree::<i32>::deserialize_compact(&s).unwrap();
+        assert!(u.structurally_eq(t), "{}", s);
+        assert_eq!(u.serialize_compact(), s);
+    }
+}
+
+#[test]
+fn deserialize_compact_malformed() {
+    type T = BinaryTree<i32>;
+    assert_eq!(T::deserialize_compact("").unwrap_err(), ParseError::UnexpectedEnd);
+    assert_eq!(T::deserialize_compact("5(3").unwrap_err(), ParseError::UnexpectedEnd);
+    assert_eq!(T::deserialize_compact("5(3,)").unwrap_err(), ParseError::UnexpectedChar(4));
+    assert_eq!(T::deserialize_compact("5(3,8").unwrap_err(), ParseError::UnexpectedEnd);
+    assert_eq!(T::deserialize_compact("5(3,8))").unwrap_err(), ParseError::UnexpectedChar(6));
+    assert_eq!(T::deserialize_compact("5(x,8)").unwrap_err(), ParseError::InvalidValue(2));
+    assert_eq!(T::deserialize_compact("5(8,3)").unwrap_err(), ParseError::Unordered);
+    assert_eq!(T::deserialize_compact("5(5,#)").unwrap_err(), ParseError::Unordered);
+}
+
+#[test]
+fn diameter_endpoints() {
     let mut t = BinaryTree::<i32>::new();
-    // Set the tree up how we want.
-    assert!(t.insert(5).is_ok());
+    assert_eq!(t.diameter_endpoints(), None);
+
+    assert!(t.insert(10).is_ok());
+    assert_eq!(t.diameter_endpoints(), Some((10, 10)));
+
+    //             10
+    //          5      15
+    //       2     8
+    //     1     7   9
+    //   0     6
+    // The longest path runs from 0 to 6 and never touches the root.
+    for x in &[5, 15, 2, 8, 1, 7, 9, 0, 6] {
+        assert!(t.insert(*x).is_ok());
+    }
+    assert_eq!(t.diameter_endpoints(), Some((0, 6)));
+
+    // A skewed tree runs from end to end.
+    let mut s = BinaryTree::<i32>::new();
+    for x in 0..10 {
+        assert!(s.insert(x).is_ok());
+    }
+    assert_eq!(s.diameter_endpoints(), Some((0, 9)));
+}
+
+#[test]
+fn kth_smallest_across_disjoint() {
+    let mut a = BinaryTree::<i32>::new();
+    let mut b = BinaryTree::<i32>::new();
+    for x in &[5, 1, 9] {
+        assert!(a.insert(*x).is_ok());
+    }
+    for x in &[4, 2, 12, 6] {
+        assert!(b.insert(*x).is_ok());
+    }
+
+    // Merged: 1 2 4 5 6 9 12
+    assert_eq!(kth_smallest_across(&a, &b, 0), Some(1));
+    assert_eq!(kth_smallest_across(&a, &b, 2), Some(4));
+    assert_eq!(kth_smallest_across(&a, &b, 5), Some(9));
+    assert_eq!(kth_smallest_across(&a, &b, 6), Some(12));
+    assert_eq!(kth_smallest_across(&a, &b, 7), None);
+    assert_eq!(kth_smallest_across(&a, &BinaryTree::new(), 1), Some(5));
+}
+
+#[test]
+fn kth_smallest_across_overlapping() {
+    let mut a = BinaryTree::<i32>::new();
+    let mut b = BinaryTree::<i32>::new();
+    for x in &[3, 1, 5] {
+        assert!(a.insert(*x).is_ok());
+    }
+    for x in &[3, 5, 7] {
+        assert!(b.insert(*x).is_ok());
+    }
+
+    // Merged: 1 3 3 5 5 7
+    let merged: Vec<_> = (0..7).map(|k| kth_smallest_across(&a, &b, k)).collect();
+    assert_eq!(merged, vec![Some(1), Some(3), Some(3), Some(5), Some(5), Some(7), None]);
+}
+
+#[test]
+fn count_inversions_sorted() {
+    assert_eq!(count_inversions::<i32>(&[]), 0);
+    assert_eq!(count_inversions(&[7]), 0);
+    assert_eq!(count_inversions(&[1, 2, 3, 4, 5]), 0);
+    assert_eq!(count_inversions(&[1, 2, 2, 3, 3, 3]), 0);
+}
+
+#[test]
+fn count_inversions_reversed() {
+    let items: Vec<i32> = (0..100).rev().collect();
+    assert_eq!(count_inversions(&items), 100 * 99 / 2);
+}
+
+#[test]
+fn count_inversions_mixed() {
+    // 3 before 1 and 2, 5 before 2 and 4.
+    assert_eq!(count_inversions(&[3, 1, 5, 2, 4]), 4);
+    // Equal items aren't inversions, but other larger ones still count.
+    assert_eq!(count_inversions(&[2, 2, 1, 2]), 2);
+}
+
+#[test]
+fn stats_track_inserts_and_removes() {
+    fn levels(n: Option<&Node<i32>>) -> usize {
+        n.map_or(0, |n| 1 + cmp::max(levels(n.left.as_deref()), levels(n.right.as_deref())))
+    }
+
+    fn check(t: &BinaryTree<i32>) {
+        let mut stack = vec![&t.root];
+        while let Some(n) = stack.pop() {
+            let expected = n.count
+                + n.left.as_ref().map_or(0, |l| l.size)
+                + n.right.as_ref().map_or(0, |r| r.size);
+            assert_eq!(n.size, expected);
+            assert_eq!(n.levels, levels(Some(n)));
+            stack.extend(n.left.as_deref());
+            stack.extend(n.right.as_deref());
+        }
+    }
+
+    let mut t = BinaryTree::<i32>::new_multiset();
+    for x in &[5, 2, 8, 1, 4, 7, 9, 3, 6, 4, 4, 8] {
+        assert!(t.insert(*x).is_ok());
+        check(&t);
+    }
+    assert_eq!(t.root.size, 12);
+
+    for x in &[5, 4, 2, 10, 8, 8, 1] {
+        let _ = t.remove(*x);
+        check(&t);
+    }
+    assert_eq!(t.root.size, t.iter().count());
+}
+
+#[test]
+fn lazy_remove() {
+    let mut t = BinaryTree::<i32>::new_lazy();
+    for x in &[5, 3, 8, 1, 4, 7, 9] {
+        assert!(t.insert(*x).is_ok());
+    }
+
+    assert_eq!(t.remove(3), Ok(3));
+    assert_eq!(t.remove(3), Err(3));
+    assert_eq!(t.remove(9), Ok(9));
+
+    // Tombstones stay in the structure, but can't be seen.
+    assert_eq!(t.node_count(), 7);
+    assert_eq!(t.len(), 5);
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(), vec![1, 4, 5, 7, 8]);
+    assert_eq!(t.contains_batch_sorted(&[1, 3, 4, 9]), vec![true, false, true, false]);
+    assert!(!t.contains(3));
+    assert!(t.contains(4));
+    assert_eq!(t.floor(3), Some(1));
+    assert_eq!(t.ceil(9), None);
+
+    // Reinserting revives the node in place.
     assert!(t.insert(3).is_ok());
-    assert!(t.insert(1).is_ok());
-    assert!(t.insert(4).is_ok());
-    assert!(t.insert(8).is_ok());
+    assert!(t.insert(3).is_err());
+    assert_eq!(t.node_count(), 7);
+    assert_eq!(t.floor(3), Some(3));
+}
 
-    // Check the tree is how we want.
-    assert_eq!(t.val.unwrap(), 5);
-    assert_eq!(t.left.as_ref().unwrap().val.unwrap(), 3);
-    assert_eq!(t.left.as_ref().unwrap().left.as_ref().unwrap().val.unwrap(), 1);
-    assert_eq!(t.left.as_ref().unwrap().right.as_ref().unwrap().val.unwrap(), 4);
-    assert_eq!(t.right.as_ref().unwrap().val.unwrap(), 8);
+#[test]
+fn lazy_compact() {
+    let mut t = BinaryTree::<i32>::new_lazy();
+    for x in 0..20 {
+        assert!(t.insert(x).is_ok());
+    }
+    for x in 0..20 {
+        if x % 3 != 0 {
+            assert_eq!(t.remove(x), Ok(x));
+        }
+    }
+    assert_eq!(t.node_count(), 20);
+    assert_eq!(t.height(), 19);
 
-    // Remove the root.
-    assert!(t.remove(5).is_ok());
+    t.compact();
+    assert_eq!(t.node_count(), 7);
+    assert_eq!(t.root.size, 7);
+    assert_eq!(t.height(), 2);
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(), vec![0, 3, 6, 9, 12, 15, 18]);
 
-    // Check the tree is how we expect.
-    assert_eq!(t.val.unwrap(), 4);
-    assert_eq!(t.left.as_ref().unwrap().val.unwrap(), 3);
-    assert_eq!(t.left.as_ref().unwrap().left.as_ref().unwrap().val.unwrap(), 1);
-    assert!(t.left.as_ref().unwrap().right.is_none());
-    assert_eq!(t.right.as_ref().unwrap().val.unwrap(), 8);
+    // Still lazy afterwards.
+    assert_eq!(t.remove(9), Ok(9));
+    assert_eq!(t.node_count(), 7);
+}
+
+#[test]
+fn floor_ceil() {
+    let mut t = BinaryTree::<i32>::new();
+    for x in &[7, 1, 10, 4] {
+        assert!(t.insert(*x).is_ok());
+    }
+
+    // Exact hits.
+    assert_eq!(t.floor(4), Some(4));
+    assert_eq!(t.ceil(4), Some(4));
+    assert_eq!(t.floor(1), Some(1));
+    assert_eq!(t.ceil(10), Some(10));
+
+    // Between values.
+    assert_eq!(t.floor(5), Some(4));
+    assert_eq!(t.ceil(5), Some(7));
+    assert_eq!(t.floor(9), Some(7));
+    assert_eq!(t.ceil(9), Some(10));
+
+    // Out of range.
+    assert_eq!(t.floor(0), None);
+    assert_eq!(t.ceil(0), Some(1));
+    assert_eq!(t.floor(11), Some(10));
+    assert_eq!(t.ceil(11), None);
+}
+
+#[test]
+fn floor_ceil_multiset() {
+    let mut t = BinaryTree::<i32>::new_multiset();
+    for x in &[7, 1, 10, 4, 4] {
+        assert!(t.insert(*x).is_ok());
+    }
+
+    assert_eq!(t.floor(5), Some(4));
+    assert_eq!(t.ceil(5), Some(7));
+    assert_eq!(t.floor(4), Some(4));
+    assert_eq!(t.ceil(4), Some(4));
+    assert_eq!(t.floor(0), None);
+    assert_eq!(t.ceil(11), None);
+    assert_eq!(BinaryTree::<i32>::new().floor(1), None);
+}
+
+#[test]
+fn successor_predecessor() {
+    let mut t = BinaryTree::<i32>::new();
+    assert_eq!(t.successor(1), None);
+    assert_eq!(t.predecessor(1), None);
+
+    for x in &[10, 5, 15, 3, 7, 20] {
+        assert!(t.insert(*x).is_ok());
+    }
+
+    // Values in the tree.
+    assert_eq!(t.successor(7), Some(10));
+    assert_eq!(t.predecessor(10), Some(7));
+    assert_eq!(t.successor(15), Some(20));
+    assert_eq!(t.predecessor(5), Some(3));
+
+    // Values between those in the tree.
+    assert_eq!(t.successor(8), Some(10));
+    assert_eq!(t.predecessor(8), Some(7));
+
+    // At and beyond the extremes.
+    assert_eq!(t.successor(20), None);
+    assert_eq!(t.predecessor(3), None);
+    assert_eq!(t.successor(0), Some(3));
+    assert_eq!(t.predecessor(99), Some(20));
+}
+
+#[test]
+fn iter_max_stack_depth() {
+    let mut skewed = BinaryTree::<i32>::new();
+    for x in (0..100).rev() {
+        assert!(skewed.insert(x).is_ok());
+    }
+    let mut it = skewed.iter();
+    assert_eq!(it.by_ref().count(), 100);
+    assert_eq!(it.max_stack_depth(), skewed.height() + 1);
+    assert_eq!(it.max_stack_depth(), 100);
+
+    // 127 values fill a perfect tree of 7 levels.
+    let values: Vec<i32> = (0..127).collect();
+    let balanced = BinaryTree::from_sorted_slice(&values);
+    let mut it = balanced.iter();
+    assert_eq!(it.by_ref().count(), 127);
+    assert_eq!(it.max_stack_depth(), 7);
+
+    assert_eq!(BinaryTree::<i32>::new().iter().max_stack_depth(), 0);
+}
+
+#[test]
+fn balanced() {
+    fn check_avl(t: &BinaryTree<i32>) {
+        let mut stack = vec![&t.root];
+        while let Some(n) = stack.pop() {
+            assert!(n.balance_factor().abs() <= 1);
+            stack.extend(n.left.as_deref());
+            stack.extend(n.right.as_deref());
+        }
+    }
+
+    let mut t = BinaryTree::<i32>::new_balanced();
+    for x in 1..1001 {
+        assert!(t.insert(x).is_ok());
+    }
+    assert!(t.insert(500).is_err());
+    assert!(t.contains(1000));
+    check_avl(&t);
+
+    // Comfortably under 2 * log2(1000).
+    assert!(t.height() < 20);
+    assert!(t.iter().cloned().eq(1..1001));
+
+    for x in 1..1001 {
+        if x % 3 != 0 {
+            assert_eq!(t.remove(x), Ok(x));
+        }
+    }
+    check_avl(&t);
+    assert_eq!(t.len(), 333);
+    assert!(t.height() < 17);
+    assert!(t.iter().cloned().eq((1..334).map(|x| x * 3)));
+    assert!(!t.contains(1));
+    assert!(t.contains(999));
+
+    // Descending and zig-zag orders need the double rotations.
+    let mut d = BinaryTree::<i32>::new_balanced();
+    for i in 0..500 {
+        let x = if i % 2 == 0 { 1000 - i } else { i };
+        assert!(d.insert(x).is_ok());
+        check_avl(&d);
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trip() {
+    let t = sample_tree();
+
+    // Written out in order, whatever the shape.
+    let json = serde_json::to_string(&t).unwrap();
+    assert_eq!(json, "[1,2,3,4,5,8,9]");
+
+    let back: BinaryTree<i32> = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.to_sorted_vec(), t.to_sorted_vec());
+    assert_eq!(back.height(), 2);
+
+    // Unordered input is fine, and duplicates are dropped.
+    let t: BinaryTree<i32> = serde_json::from_str("[7, 2, 7, 5, 2]").unwrap();
+    assert_eq!(t.to_sorted_vec(), vec![2, 5, 7]);
+
+    let empty: BinaryTree<String> = serde_json::from_str("[]").unwrap();
+    assert!(empty.is_empty());
+    assert_eq!(serde_json::to_string(&empty).unwrap(), "[]");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_malformed() {
+    assert!(serde_json::from_str::<BinaryTree<i32>>("[1, 2").is_err());
+    assert!(serde_json::from_str::<BinaryTree<i32>>("[1, \"two\"]").is_err());
+    assert!(serde_json::from_str::<BinaryTree<i32>>("{\"val\": 1}").is_err());
+}
+
+}}
+
+#[cfg(test)]
+mod boxed {
+    use super::*;
+
+    type BinaryTree<T, C = Natural> = super::BinaryTree<T, C, BoxAlloc>;
+
+    tree_tests!();
+}
+
+#[cfg(test)]
+mod pooled {
+    use super::*;
+    use nodealloc::PoolAlloc;
+
+    type BinaryTree<T, C = Natural> = super::BinaryTree<T, C, PoolAlloc<Node<T>>>;
+
+    tree_tests!();
+}
+
+#[test]
+fn pool_alloc_reuses_nodes() {
+    let mut t = BinaryTree::<i32>::new().with_alloc(::nodealloc::PoolAlloc::new());
+    for x in 0..10 {
+        assert!(t.insert(x).is_ok());
+    }
+    // The root is held in the tree, and the nine below it were allocated fresh.
+    assert_eq!(t.alloc().available(), 0);
+
+    for x in 0..5 {
+        assert_eq!(t.remove(x), Ok(x));
+    }
+    assert_eq!(t.alloc().available(), 5);
+
+    // New nodes take the freed boxes first.
+    for x in 10..13 {
+        assert!(t.insert(x).is_ok());
+    }
+    assert_eq!(t.alloc().available(), 2);
+
+    // Rebuilding hands the old nodes back before taking boxes for the new ones.
+    t.compact();
+    assert_eq!(t.alloc().available(), 2);
+    assert!(t.iter().cloned().eq(5..13));
+
+    // Walking with `iter_in_place` borrows a box for the root, and gives it back.
+    assert!(t.iter_in_place().eq(5..13));
+    assert_eq!(t.alloc().available(), 2);
+
+    t.clear();
+    assert_eq!(t.alloc().available(), 9);
+
+    // A multiset node's box is only given back with its last copy.
+    let mut m = BinaryTree::<i32>::new_multiset().with_alloc(::nodealloc::PoolAlloc::new());
+    for x in &[2, 1, 1, 3] {
+        assert!(m.insert(*x).is_ok());
+    }
+    assert_eq!(m.remove(1), Ok(1));
+    assert_eq!(m.alloc().available(), 0);
+    assert_eq!(m.remove(1), Ok(1));
+    assert_eq!(m.alloc().available(), 1);
+    assert_eq!(m.to_sorted_vec(), vec![2, 3]);
 }
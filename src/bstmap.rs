@@ -0,0 +1,306 @@
+//! Simple ordered map, stored as an unbalanced binary search tree on the keys.
+//!
+//! # Examples
+//!
+//! ```
+//! use algorithms::bstmap::BstMap;
+//!
+//! let mut m = BstMap::<i32, &'static str>::new();
+//! m.insert(2, "two");
+//! m.insert(1, "one");
+//!
+//! assert_eq!(m.get(1), Some(&"one"));
+//! assert_eq!(m.remove(2), Some("two"));
+//! assert_eq!(m.get(2), None);
+//! ```
+
+use std::cmp::Ordering;
+
+use stack::Stack;
+
+#[derive(Debug)]
+pub struct BstMap<K, V> where K: Ord + Copy {
+    root: Link<K, V>,
+    len: usize,
+}
+
+type Link<K, V> = Option<Box<MapNode<K, V>>>;
+
+#[derive(Debug)]
+struct MapNode<K, V> {
+    key: K,
+    val: V,
+    left: Link<K, V>,
+    right: Link<K, V>,
+}
+
+impl<K, V> MapNode<K, V> {
+    fn new(key: K, val: V) -> MapNode<K, V> {
+        MapNode { key, val, left: None, right: None }
+    }
+}
+
+impl<K, V> BstMap<K, V> where K: Ord + Copy {
+    pub fn new() -> BstMap<K, V> {
+        BstMap{ root: None, len: 0 }
+    }
+
+    /// Build a height-balanced map from pairs in strictly ascending key order,
+    /// by making the middle pair the root and recursing either side.
+    pub fn from_sorted_pairs(pairs: &[(K, V)]) -> BstMap<K, V> where V: Clone {
+        debug_assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0),
+                      "from_sorted_pairs needs strictly ascending keys");
+        BstMap{ root: build_balanced(pairs), len: pairs.len() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Insert a value under the given key.
+    /// If the key was already present, replaces its value and returns the old one.
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let mut link = &mut self.root;
+        while let Some(ref mut node) = *link {
+            link = match key.cmp(&node.key) {
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => &mut node.right,
+                Ordering::Equal => {
+                    return Some(::std::mem::replace(&mut node.val, val));
+                },
+            };
+        }
+
+        *link = Some(Box::new(MapNode::new(key, val)));
+        self.len += 1;
+        None
+    }
+
+    pub fn get(&self, key: K) -> Option<&V> {
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            link = match key.cmp(&node.key) {
+                Ordering::Less => &node.left,
+                Ordering::Greater => &node.right,
+                Ordering::Equal => return Some(&node.val),
+            };
+        }
+        None
+    }
+
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        let mut link = &mut self.root;
+        while let Some(ref mut node) = *link {
+            link = match key.cmp(&node.key) {
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => &mut node.right,
+                Ordering::Equal => return Some(&mut node.val),
+            };
+        }
+        None
+    }
+
+    pub fn contains_key(&self, key: K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Remove the entry for the given key, returning its value if it was present.
+    pub fn remove(&mut self, key: K) -> Option<V> {
+        let val = remove_from(&mut self.root, key);
+        if val.is_some() {
+            self.len -= 1;
+        }
+        val
+    }
+}
+
+impl<K, V> Default for BstMap<K, V> where K: Ord + Copy {
+    fn default() -> BstMap<K, V> {
+        BstMap::new()
+    }
+}
+
+/// Frees the nodes one at a time from a work stack, as keys inserted in order
+/// leave a chain which would overflow the stack if each box dropped its own children.
+impl<K, V> Drop for BstMap<K, V> where K: Ord + Copy {
+    fn drop(&mut self) {
+        let mut stack = Stack::new();
+        if let Some(root) = self.root.take() {
+            stack.push(root);
+        }
+
+        // Each node is detached from its children, so drops without recursing.
+        while let Some(mut node) = stack.pop() {
+            if let Some(l) = node.left.take() {
+                stack.push(l);
+            }
+            if let Some(r) = node.right.take() {
+                stack.push(r);
+            }
+        }
+    }
+}
+
+fn build_balanced<K, V>(pairs: &[(K, V)]) -> Link<K, V> where K: Copy, V: Clone {
+    if pairs.is_empty() {
+        return None;
+    }
+
+    let mid = pairs.len() / 2;
+    let mut node = MapNode::new(pairs[mid].0, pairs[mid].1.clone());
+    node.left = build_balanced(&pairs[..mid]);
+    node.right = build_balanced(&pairs[mid + 1..]);
+    Some(Box::new(node))
+}
+
+// Remove the node with the given key from the subtree hanging off `link`.
+// Walks down in a loop rather than recursing, as the tree may be a long chain.
+fn remove_from<K, V>(mut link: &mut Link<K, V>, key: K) -> Option<V> where K: Ord {
+    loop {
+        let ord = match *link {
+            None => return None,
+            Some(ref node) => key.cmp(&node.key),
+        };
+        link = match ord {
+            Ordering::Less => &mut link.as_mut().unwrap().left,
+            Ordering::Greater => &mut link.as_mut().unwrap().right,
+            Ordering::Equal => break,
+        };
+    }
+
+    let mut node = link.take().unwrap();
+    *link = match (node.left.take(), node.right.take()) {
+        (None, None) => None,
+        (Some(t), None) | (None, Some(t)) => Some(t),
+
+        // Both subtrees present, so replace the node with
+        // the smallest entry of its right subtree.
+        (Some(l), Some(r)) => {
+            let (mut min, rest) = take_min(r);
+            min.left = Some(l);
+            min.right = rest;
+            Some(min)
+        },
+    };
+    Some(node.val)
+}
+
+// Detach the smallest node of a subtree.
+// Returns it along with what remains of the subtree.
+fn take_min<K, V>(mut node: Box<MapNode<K, V>>) -> (Box<MapNode<K, V>>, Link<K, V>) {
+    if node.left.is_none() {
+        let rest = node.right.take();
+        return (node, rest);
+    }
+
+    // Step down to the parent of the smallest node, which takes its right subtree.
+    let mut parent = &mut node;
+    while parent.left.as_ref().unwrap().left.is_some() {
+        parent = parent.left.as_mut().unwrap();
+    }
+    let mut min = parent.left.take().unwrap();
+    parent.left = min.right.take();
+    (min, Some(node))
+}
+
+// Number of keys in the chains used to check the map never recurses down them.
+#[cfg(test)]
+const DEEP: i32 = 10_000;
+
+#[test]
+fn insert_get() {
+    let mut m = BstMap::<i32, i32>::new();
+    assert!(m.is_empty());
+    assert_eq!(m.insert(5, 50), None);
+    assert_eq!(m.insert(3, 30), None);
+    assert_eq!(m.insert(8, 80), None);
+    assert_eq!(m.len(), 3);
+
+    assert_eq!(m.get(3), Some(&30));
+    assert_eq!(m.get(4), None);
+
+    // Replacing a value keeps the length the same.
+    assert_eq!(m.insert(3, 31), Some(30));
+    assert_eq!(m.get(3), Some(&31));
+    assert_eq!(m.len(), 3);
+
+    *m.get_mut(8).unwrap() += 1;
+    assert_eq!(m.get(8), Some(&81));
+}
+
+#[test]
+fn remove() {
+    let mut m = BstMap::<i32, i32>::new();
+    for k in &[5, 3, 8, 1, 4, 7, 9, 6] {
+        m.insert(*k, *k * 10);
+    }
+
+    assert_eq!(m.remove(2), None);
+
+    // Both children, with the successor deeper down the right subtree.
+    assert_eq!(m.remove(5), Some(50));
+    assert_eq!(m.len(), 7);
+    for k in &[1, 3, 4, 6, 7, 8, 9] {
+        assert_eq!(m.get(*k), Some(&(*k * 10)));
+    }
+
+    for k in &[1, 3, 4, 6, 7, 8, 9] {
+        assert_eq!(m.remove(*k), Some(*k * 10));
+    }
+    assert!(m.is_empty());
+    assert_eq!(m.get(9), None);
+}
+
+#[test]
+fn from_sorted_pairs() {
+    fn height<K, V>(link: &Link<K, V>) -> usize {
+        match *link {
+            None => 0,
+            Some(ref n) => 1 + ::std::cmp::max(height(&n.left), height(&n.right)),
+        }
+    }
+
+    let pairs: Vec<(i32, String)> = (1..1001).map(|k| (k, k.to_string())).collect();
+    let m = BstMap::from_sorted_pairs(&pairs);
+    assert_eq!(m.len(), 1000);
+
+    // 1000 entries fit in 10 levels.
+    assert_eq!(height(&m.root), 10);
+    for k in &[1, 500, 777, 1000] {
+        assert_eq!(m.get(*k), Some(&k.to_string()));
+    }
+    assert_eq!(m.get(0), None);
+    assert_eq!(m.get(1001), None);
+
+    assert!(BstMap::<i32, i32>::from_sorted_pairs(&[]).is_empty());
+}
+
+#[test]
+fn deep_chain() {
+    // Run on a thread with a small stack, where recursing down the chain
+    // would overflow long before reaching the bottom.
+    let worker = ::std::thread::Builder::new().stack_size(64 * 1024).spawn(|| {
+        // 0 has -1 on its left, and on its right a chain stepping left
+        // from DEEP - 1 down to 1, its successor.
+        let mut m = BstMap::<i32, i32>::new();
+        m.insert(0, 0);
+        m.insert(-1, -1);
+        for k in (1..DEEP).rev() {
+            m.insert(k, k * 10);
+        }
+
+        assert_eq!(m.remove(0), Some(0));
+        assert_eq!(m.remove(2), Some(20));
+        assert_eq!(m.remove(DEEP), None);
+        assert_eq!(m.len(), DEEP as usize - 1);
+        for k in &[-1, 1, 3, DEEP - 1] {
+            assert!(m.contains_key(*k));
+        }
+        drop(m);
+    }).unwrap();
+    assert!(worker.join().is_ok());
+}
@@ -0,0 +1,105 @@
+//! Complete binary tree over arbitrary data, laid out in level order as for a heap.
+//!
+//! Unlike `BinaryTree` the values aren't ordered in any way; the shape is fixed by
+//! the number of items, with the children of item `i` at `2i + 1` and `2i + 2`.
+//!
+//! # Examples
+//!
+//! ```
+//! use algorithms::completetree::CompleteTree;
+//!
+//! let t = CompleteTree::from_level_order(&['a', 'b', 'c', 'd']);
+//!
+//! assert_eq!(t.children(0), (Some(1), Some(2)));
+//! assert_eq!(t.children(1), (Some(3), None));
+//! assert_eq!(t.parent(3), Some(1));
+//! assert_eq!(t.get(3), Some(&'d'));
+//! ```
+
+#[derive(Clone, Debug)]
+pub struct CompleteTree<T> {
+    items: Vec<T>,
+}
+
+impl<T> CompleteTree<T> {
+    /// Build a complete tree holding the items in level order.
+    pub fn from_level_order(items: &[T]) -> CompleteTree<T> where T: Clone {
+        CompleteTree{ items: items.to_vec() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn get(&self, i: usize) -> Option<&T> {
+        self.items.get(i)
+    }
+
+    /// The items in level order, which is also their index order.
+    pub fn level_order(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Index of the parent of item `i`.
+    /// `None` for the root, or if there is no item `i`.
+    pub fn parent(&self, i: usize) -> Option<usize> {
+        if i == 0 || i >= self.items.len() {
+            None
+        } else {
+            Some((i - 1) / 2)
+        }
+    }
+
+    /// Indices of the left and right children of item `i`, where present.
+    pub fn children(&self, i: usize) -> (Option<usize>, Option<usize>) {
+        let child = |c: usize| if c < self.items.len() { Some(c) } else { None };
+        if i >= self.items.len() {
+            return (None, None);
+        }
+        (child(2 * i + 1), child(2 * i + 2))
+    }
+}
+
+#[test]
+fn index_math() {
+    let t = CompleteTree::from_level_order(&[10, 20, 30, 40, 50, 60]);
+    assert_eq!(t.len(), 6);
+
+    assert_eq!(t.parent(0), None);
+    assert_eq!(t.parent(1), Some(0));
+    assert_eq!(t.parent(2), Some(0));
+    assert_eq!(t.parent(4), Some(1));
+    assert_eq!(t.parent(5), Some(2));
+    assert_eq!(t.parent(6), None);
+
+    assert_eq!(t.children(0), (Some(1), Some(2)));
+    assert_eq!(t.children(1), (Some(3), Some(4)));
+    assert_eq!(t.children(2), (Some(5), None));
+    assert_eq!(t.children(3), (None, None));
+    assert_eq!(t.children(6), (None, None));
+
+    // Every child points back at its parent.
+    for i in 0..t.len() {
+        let (l, r) = t.children(i);
+        for c in l.into_iter().chain(r) {
+            assert_eq!(t.parent(c), Some(i));
+        }
+    }
+}
+
+#[test]
+fn level_order() {
+    let t = CompleteTree::from_level_order(&["root", "l", "r", "ll"]);
+    assert_eq!(t.level_order(), &["root", "l", "r", "ll"]);
+    assert_eq!(t.get(t.children(0).1.unwrap()), Some(&"r"));
+    assert_eq!(t.get(t.parent(3).unwrap()), Some(&"l"));
+
+    let e = CompleteTree::<i32>::from_level_order(&[]);
+    assert!(e.is_empty());
+    assert_eq!(e.get(0), None);
+    assert_eq!(e.children(0), (None, None));
+}
@@ -0,0 +1,127 @@
+//! Set of disjoint closed intervals, answering point membership queries.
+//!
+//! The start and end points are kept in two separate `BinaryTree`s, so a query is
+//! a floor lookup for the nearest start followed by a ceiling lookup for its end.
+//!
+//! # Examples
+//!
+//! ```
+//! use algorithms::intervalset::IntervalSet;
+//!
+//! let mut s = IntervalSet::<i32>::new();
+//! assert!(s.insert(1, 3).is_ok());
+//! assert!(s.insert(10, 20).is_ok());
+//!
+//! assert!(s.covers(2));
+//! assert!(s.covers(10));
+//! assert!(!s.covers(5));
+//! ```
+
+use binarytree::BinaryTree;
+
+#[derive(Debug)]
+pub struct IntervalSet<T> where T: Ord + Copy {
+    starts: BinaryTree<T>,
+    ends: BinaryTree<T>,
+    len: usize,
+}
+
+impl<T> IntervalSet<T> where T: Ord + Copy {
+    pub fn new() -> IntervalSet<T> {
+        IntervalSet{ starts: BinaryTree::new(), ends: BinaryTree::new(), len: 0 }
+    }
+
+    /// Number of intervals in the set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Add the closed interval `[start, end]`.
+    /// Errors with the given bounds if `start > end`, or if the interval
+    /// would overlap (or share an endpoint with) one already in the set.
+    pub fn insert(&mut self, start: T, end: T) -> Result<(), (T, T)> {
+        if start > end || self.covers(start) || self.covers(end) {
+            return Err((start, end));
+        }
+
+        // Reject an existing interval lying wholly inside the new one.
+        if let Some(s) = self.starts.ceil(start) {
+            if s <= end {
+                return Err((start, end));
+            }
+        }
+
+        // Can't fail, since neither point was covered.
+        let _ = self.starts.insert(start);
+        let _ = self.ends.insert(end);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Check whether `point` falls within any interval, including on its bounds.
+    pub fn covers(&self, point: T) -> bool {
+        // The only interval which might cover the point is the one starting closest to its left.
+        let start = match self.starts.floor(point) {
+            None => return false,
+            Some(s) => s,
+        };
+
+        // Intervals don't overlap, so the first end at or after the start belongs to it.
+        match self.ends.ceil(start) {
+            None => false,
+            Some(end) => point <= end,
+        }
+    }
+}
+
+impl<T> Default for IntervalSet<T> where T: Ord + Copy {
+    fn default() -> IntervalSet<T> {
+        IntervalSet::new()
+    }
+}
+
+#[test]
+fn covers() {
+    let mut s = IntervalSet::<i32>::new();
+    assert!(!s.covers(0));
+
+    assert!(s.insert(10, 20).is_ok());
+    assert!(s.insert(1, 3).is_ok());
+    assert!(s.insert(30, 30).is_ok());
+    assert_eq!(s.len(), 3);
+
+    // Inside.
+    assert!(s.covers(2));
+    assert!(s.covers(15));
+
+    // On the boundary.
+    for p in &[1, 3, 10, 20, 30] {
+        assert!(s.covers(*p));
+    }
+
+    // In the gaps, and off either end.
+    for p in &[0, 4, 9, 21, 29, 31] {
+        assert!(!s.covers(*p));
+    }
+}
+
+#[test]
+fn insert_overlapping() {
+    let mut s = IntervalSet::<i32>::new();
+    assert!(s.insert(10, 20).is_ok());
+
+    assert_eq!(s.insert(5, 4), Err((5, 4)));
+    assert_eq!(s.insert(15, 25), Err((15, 25)));
+    assert_eq!(s.insert(5, 10), Err((5, 10)));
+    assert_eq!(s.insert(5, 25), Err((5, 25)));
+    assert_eq!(s.insert(12, 18), Err((12, 18)));
+    assert_eq!(s.len(), 1);
+
+    assert!(s.insert(5, 9).is_ok());
+    assert!(s.insert(21, 25).is_ok());
+    assert_eq!(s.len(), 3);
+}
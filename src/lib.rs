@@ -1,2 +1,14 @@
+#[cfg(feature = "serde")]
+extern crate serde;
+
 pub mod stack;
 pub mod binarytree;
+pub mod bstmap;
+pub mod lru;
+pub mod num;
+pub mod arenatree;
+pub mod completetree;
+pub mod intervalset;
+pub mod nodealloc;
+pub mod parens;
+pub mod queue;
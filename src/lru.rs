@@ -0,0 +1,150 @@
+//! Capacity-bounded least-recently-used cache.
+//!
+//! Entries are looked up through a `BstMap`, while a `Stack` of keys keeps
+//! them in order of use with the most recently used on top.
+//! Touching an entry walks the recency stack, so this is intended for small caches.
+//!
+//! # Examples
+//!
+//! ```
+//! use algorithms::lru::LruCache;
+//!
+//! let mut c = LruCache::<i32, &'static str>::new(2);
+//! c.put(1, "one");
+//! c.put(2, "two");
+//! c.get(1);
+//!
+//! // 2 is now the least recently used, so makes way for 3.
+//! assert_eq!(c.put(3, "three"), Some((2, "two")));
+//! assert_eq!(c.get(2), None);
+//! assert_eq!(c.get(1), Some(&"one"));
+//! ```
+
+use bstmap::BstMap;
+use stack::Stack;
+
+#[derive(Debug)]
+pub struct LruCache<K, V> where K: Ord + Copy {
+    capacity: usize,
+    map: BstMap<K, V>,
+    recency: Stack<K>,
+}
+
+impl<K, V> LruCache<K, V> where K: Ord + Copy {
+    /// Create a cache holding at most `capacity` entries.
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        assert!(capacity > 0, "LruCache capacity must be non-zero");
+        LruCache{ capacity, map: BstMap::new(), recency: Stack::new() }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Look up a key, marking it as the most recently used.
+    pub fn get(&mut self, key: K) -> Option<&V> {
+        if self.map.contains_key(key) {
+            self.touch(key);
+        }
+        self.map.get(key)
+    }
+
+    /// Insert or update an entry, marking it as the most recently used.
+    /// If the cache was full, evicts and returns the least recently used entry.
+    pub fn put(&mut self, key: K, val: V) -> Option<(K, V)> {
+        if self.map.contains_key(key) {
+            self.map.insert(key, val);
+            self.touch(key);
+            return None;
+        }
+
+        let evicted = if self.map.len() == self.capacity {
+            let old = self.take_least_recent();
+            let old_val = self.map.remove(old).unwrap();
+            Some((old, old_val))
+        } else {
+            None
+        };
+
+        self.map.insert(key, val);
+        self.recency.push(key);
+        evicted
+    }
+
+    // Move a key already in the cache to the top of the recency stack.
+    fn touch(&mut self, key: K) {
+        let mut above = Stack::new();
+        while let Some(k) = self.recency.pop() {
+            if k == key {
+                break;
+            }
+            above.push(k);
+        }
+
+        for k in above {
+            self.recency.push(k);
+        }
+        self.recency.push(key);
+    }
+
+    // Remove and return the key at the bottom of the recency stack.
+    fn take_least_recent(&mut self) -> K {
+        let mut reversed = Stack::new();
+        while let Some(k) = self.recency.pop() {
+            reversed.push(k);
+        }
+
+        let least = reversed.pop().unwrap();
+        for k in reversed {
+            self.recency.push(k);
+        }
+        least
+    }
+}
+
+#[test]
+fn eviction_order() {
+    let mut c = LruCache::<i32, i32>::new(3);
+    assert_eq!(c.put(1, 10), None);
+    assert_eq!(c.put(2, 20), None);
+    assert_eq!(c.put(3, 30), None);
+    assert_eq!(c.len(), 3);
+
+    // Filling past capacity evicts in insertion order.
+    assert_eq!(c.put(4, 40), Some((1, 10)));
+    assert_eq!(c.put(5, 50), Some((2, 20)));
+    assert_eq!(c.len(), 3);
+    assert_eq!(c.get(1), None);
+    assert_eq!(c.get(2), None);
+
+    // Updating an existing key doesn't evict anything.
+    assert_eq!(c.put(5, 51), None);
+    assert_eq!(c.put(6, 60), Some((3, 30)));
+}
+
+#[test]
+fn get_updates_recency() {
+    let mut c = LruCache::<i32, i32>::new(3);
+    c.put(1, 10);
+    c.put(2, 20);
+    c.put(3, 30);
+
+    // 1 becomes the most recently used, so 2 is now the oldest.
+    assert_eq!(c.get(1), Some(&10));
+    assert_eq!(c.put(4, 40), Some((2, 20)));
+    assert_eq!(c.put(5, 50), Some((3, 30)));
+    assert_eq!(c.put(6, 60), Some((1, 10)));
+
+    // Misses don't affect the order.
+    assert_eq!(c.get(7), None);
+    assert_eq!(c.put(7, 70), Some((4, 40)));
+}
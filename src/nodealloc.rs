@@ -0,0 +1,161 @@
+//! Strategies for allocating the nodes of a `BinaryTree`.
+//!
+//! Boxes always come from the global allocator on stable Rust, so a strategy
+//! can't change where a node lives, only when a fresh box is needed. `BoxAlloc`,
+//! the default, allocates a box per node and frees it as soon as the node goes.
+//! `PoolAlloc` keeps the boxes of removed nodes, emptied, to reuse for the next
+//! ones inserted, and can allocate a number up front, so a tree which stays
+//! within that size never allocates again.
+//!
+//! `Stack` keeps its values in a single `Vec` rather than in nodes, so it has
+//! no use for a strategy; `Stack::with_capacity` reserves its room up front.
+//!
+//! # Examples
+//!
+//! ```
+//! use algorithms::binarytree::BinaryTree;
+//! use algorithms::nodealloc::PoolAlloc;
+//!
+//! let mut t = BinaryTree::<i32>::new().with_alloc(PoolAlloc::with_capacity(2));
+//! for x in &[2, 1, 3] {
+//!     assert!(t.insert(*x).is_ok());
+//! }
+//! // The root is held in the tree itself, the other two took the boxes.
+//! assert_eq!(t.alloc().available(), 0);
+//!
+//! assert_eq!(t.remove(1), Ok(1));
+//! assert_eq!(t.alloc().available(), 1);
+//! ```
+
+use std::fmt;
+use std::mem;
+
+/// How the boxes for nodes of type `N` are come by and given up.
+pub trait NodeAlloc<N> {
+    /// Put a new node in a box.
+    fn alloc(&mut self, node: N) -> Box<N>;
+
+    /// Take back the box of a node which is no longer needed,
+    /// handing back what it held.
+    fn free(&mut self, node: Box<N>) -> N;
+}
+
+/// Allocates every node in a box of its own, which is dropped along with it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BoxAlloc;
+
+impl<N> NodeAlloc<N> for BoxAlloc {
+    fn alloc(&mut self, node: N) -> Box<N> {
+        Box::new(node)
+    }
+
+    fn free(&mut self, node: Box<N>) -> N {
+        *node
+    }
+}
+
+/// Keeps the boxes of freed nodes, holding an empty `N::default()`,
+/// and fills those before allocating any more.
+pub struct PoolAlloc<N> {
+    spare: Vec<Box<N>>,
+}
+
+impl<N> PoolAlloc<N> where N: Default {
+    pub fn new() -> PoolAlloc<N> {
+        PoolAlloc{ spare: Vec::new() }
+    }
+
+    /// Create a pool with `n` boxes allocated up front.
+    pub fn with_capacity(n: usize) -> PoolAlloc<N> {
+        PoolAlloc{ spare: (0..n).map(|_| Box::default()).collect() }
+    }
+
+    /// Number of boxes waiting to be reused.
+    pub fn available(&self) -> usize {
+        self.spare.len()
+    }
+}
+
+impl<N> NodeAlloc<N> for PoolAlloc<N> where N: Default {
+    fn alloc(&mut self, node: N) -> Box<N> {
+        match self.spare.pop() {
+            None => Box::new(node),
+            Some(mut b) => {
+                *b = node;
+                b
+            },
+        }
+    }
+
+    fn free(&mut self, mut node: Box<N>) -> N {
+        let val = mem::take(&mut *node);
+        self.spare.push(node);
+        val
+    }
+}
+
+impl<N> Default for PoolAlloc<N> where N: Default {
+    fn default() -> PoolAlloc<N> {
+        PoolAlloc::new()
+    }
+}
+
+/// A clone starts out with an empty pool of its own.
+impl<N> Clone for PoolAlloc<N> where N: Default {
+    fn clone(&self) -> PoolAlloc<N> {
+        PoolAlloc::new()
+    }
+}
+
+// Not derived, as the spare boxes all hold the same empty node.
+impl<N> fmt::Debug for PoolAlloc<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PoolAlloc")
+            .field("available", &self.spare.len())
+            .finish()
+    }
+}
+
+#[test]
+fn box_alloc() {
+    let mut a = BoxAlloc;
+    let b = a.alloc(5);
+    assert_eq!(*b, 5);
+    assert_eq!(a.free(b), 5);
+}
+
+#[test]
+fn pool_reuses_boxes() {
+    let mut pool = PoolAlloc::new();
+    let b = pool.alloc(String::from("a"));
+    let addr = &*b as *const String;
+    assert_eq!(pool.available(), 0);
+
+    assert_eq!(pool.free(b), "a");
+    assert_eq!(pool.available(), 1);
+
+    // The next node goes in the freed box.
+    let c = pool.alloc(String::from("c"));
+    assert_eq!(&*c as *const String, addr);
+    assert_eq!(*c, "c");
+    assert_eq!(pool.available(), 0);
+
+    // Once the pool is empty, fresh boxes are allocated.
+    let d = pool.alloc(String::from("d"));
+    assert_ne!(&*d as *const String, addr);
+}
+
+#[test]
+fn pool_with_capacity() {
+    let mut pool = PoolAlloc::<i32>::with_capacity(2);
+    assert_eq!(pool.available(), 2);
+    let a = pool.alloc(1);
+    let b = pool.alloc(2);
+    assert_eq!(pool.available(), 0);
+    assert_eq!((*a, *b), (1, 2));
+
+    // Clones don't copy the spare boxes.
+    assert_eq!(pool.free(a), 1);
+    assert_eq!(pool.clone().available(), 0);
+    assert_eq!(format!("{:?}", pool), "PoolAlloc { available: 1 }");
+}
@@ -0,0 +1,27 @@
+//! Numeric helper traits used by the aggregation methods of the collections.
+
+/// Integer types supporting overflow-aware addition.
+pub trait Integer: Copy {
+    const ZERO: Self;
+
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn saturating_add(self, other: Self) -> Self;
+}
+
+macro_rules! impl_integer {
+    ($($t:ty)*) => ($(
+        impl Integer for $t {
+            const ZERO: $t = 0;
+
+            fn checked_add(self, other: $t) -> Option<$t> {
+                <$t>::checked_add(self, other)
+            }
+
+            fn saturating_add(self, other: $t) -> $t {
+                <$t>::saturating_add(self, other)
+            }
+        }
+    )*)
+}
+
+impl_integer! { i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize }
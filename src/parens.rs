@@ -0,0 +1,75 @@
+//! Bracket matching over `()`, `[]` and `{}`, using a `Stack` of the open brackets.
+//!
+//! # Examples
+//!
+//! ```
+//! use algorithms::parens::max_nesting_depth;
+//!
+//! assert_eq!(max_nesting_depth("f(a[0], {b: (c)})"), 3);
+//! assert_eq!(max_nesting_depth("no brackets"), 0);
+//! ```
+
+use stack::Stack;
+
+// The opening bracket matching a closing one.
+fn opener(c: char) -> Option<char> {
+    match c {
+        ')' => Some('('),
+        ']' => Some('['),
+        '}' => Some('{'),
+        _ => None,
+    }
+}
+
+/// The deepest level of nested brackets in the input, ignoring any other characters.
+/// Input with no brackets at all, including empty input, gives 0.
+/// This reports a depth rather than an error for mismatched input: scanning stops
+/// at the first closing bracket which doesn't match the innermost open one, and
+/// the deepest level reached before it is returned. Unclosed brackets still count.
+pub fn max_nesting_depth(input: &str) -> usize {
+    let mut open = Stack::new();
+    let mut max = 0;
+
+    for c in input.chars() {
+        match c {
+            '(' | '[' | '{' => {
+                open.push(c);
+                max = ::std::cmp::max(max, open.len());
+            },
+            ')' | ']' | '}' if open.peek() == opener(c).as_ref() => {
+                open.pop();
+            },
+            ')' | ']' | '}' => break,
+            _ => {},
+        }
+    }
+
+    max
+}
+
+#[test]
+fn flat() {
+    assert_eq!(max_nesting_depth(""), 0);
+    assert_eq!(max_nesting_depth("abc"), 0);
+    assert_eq!(max_nesting_depth("()[]{}"), 1);
+    assert_eq!(max_nesting_depth("a(b) c[d] e{f}"), 1);
+}
+
+#[test]
+fn nested() {
+    assert_eq!(max_nesting_depth("(()[{}])"), 3);
+    assert_eq!(max_nesting_depth("x = {a: [1, (2 + (3))], b: ()}"), 4);
+
+    let deep = "(".repeat(1000) + &")".repeat(1000);
+    assert_eq!(max_nesting_depth(&deep), 1000);
+}
+
+#[test]
+fn mismatched() {
+    // Stops at the `]`, before the deeper group after it.
+    assert_eq!(max_nesting_depth("(()] ((()))"), 2);
+    // A stray closer ends the scan straight away.
+    assert_eq!(max_nesting_depth(") (())"), 0);
+    // Unclosed brackets still count towards the depth.
+    assert_eq!(max_nesting_depth("((("), 3);
+}
@@ -0,0 +1,89 @@
+//! First-in first-out queue built from a pair of `Stack`s.
+//!
+//! New values are pushed onto an inbox stack. When the outbox stack runs dry
+//! the whole inbox is popped across onto it, which flips it into arrival order,
+//! so each value is moved at most once and both operations are amortised O(1).
+//!
+//! # Examples
+//!
+//! ```
+//! use algorithms::queue::Queue;
+//!
+//! let mut q = Queue::<i32>::new();
+//! q.enqueue(1);
+//! q.enqueue(2);
+//!
+//! assert_eq!(q.dequeue(), Some(1));
+//! q.enqueue(3);
+//! assert_eq!(q.dequeue(), Some(2));
+//! assert_eq!(q.dequeue(), Some(3));
+//! assert_eq!(q.dequeue(), None);
+//! ```
+
+use stack::Stack;
+
+#[derive(Debug)]
+pub struct Queue<T> {
+    // Newest value on top.
+    inbox: Stack<T>,
+    // Oldest value on top.
+    outbox: Stack<T>,
+}
+
+impl<T> Queue<T> {
+    pub fn new() -> Queue<T> {
+        Queue{ inbox: Stack::new(), outbox: Stack::new() }
+    }
+
+    // A stack already has its newest value on top, so can serve as the inbox as it is.
+    pub(crate) fn from_stack(stack: Stack<T>) -> Queue<T> {
+        Queue{ inbox: stack, outbox: Stack::new() }
+    }
+
+    /// Add a value to the back of the queue.
+    pub fn enqueue(&mut self, val: T) {
+        self.inbox.push(val);
+    }
+
+    /// Take the value from the front of the queue, if there is one.
+    pub fn dequeue(&mut self) -> Option<T> {
+        if let Some(val) = self.outbox.pop() {
+            return Some(val);
+        }
+        while let Some(val) = self.inbox.pop() {
+            self.outbox.push(val);
+        }
+        self.outbox.pop()
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Queue<T> {
+        Queue::new()
+    }
+}
+
+/// Iterating over a queue dequeues values until it's empty.
+impl<T> Iterator for Queue<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.dequeue()
+    }
+}
+
+#[test]
+fn fifo_order() {
+    let mut q = Queue::<i32>::new();
+    assert_eq!(q.dequeue(), None);
+
+    for x in 1..4 {
+        q.enqueue(x);
+    }
+    assert_eq!(q.dequeue(), Some(1));
+
+    // Later arrivals wait behind the ones already moved across.
+    q.enqueue(4);
+    q.enqueue(5);
+    assert_eq!(q.collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+}
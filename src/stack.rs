@@ -31,44 +31,244 @@
 //! assert_eq!(v[1], "World");
 //! ```
 
-#[derive(Debug)]
-pub struct Stack<T> {
-    top: Option<Box<StackNode<T>>>,
-}
+use std::iter::Rev;
+use std::slice;
 
-#[derive(Clone,Debug)]
-struct StackNode<T> {
-    val: T,
-    next: Option<Box<StackNode<T>>>,
-}
+use binarytree::BinaryTree;
+use queue::Queue;
 
-impl <T> StackNode<T> {
-    fn new(val: T) -> StackNode<T> {
-        StackNode { val: val, next: None }
-    }
+// The values are kept in a `Vec`, bottom first, so the top of the stack is
+// its last element and pushing and popping never need to shuffle anything.
+#[derive(Debug)]
+pub struct Stack<T> {
+    items: Vec<T>,
+    // The largest `len` has ever been.
+    high_water: usize,
 }
 
 impl<T> Stack<T> {
     pub fn new() -> Stack<T> {
-        Stack{ top: None }
+        Stack{ items: Vec::new(), high_water: 0 }
+    }
+
+    /// Create an empty stack with room for at least `capacity` values,
+    /// so pushing that many won't need to reallocate.
+    pub fn with_capacity(capacity: usize) -> Stack<T> {
+        Stack{ items: Vec::with_capacity(capacity), high_water: 0 }
     }
 
     pub fn push(&mut self, val: T) {
-        let mut node = StackNode::new(val);
-        let next = self.top.take();
-        node.next = next;
-        self.top = Some(Box::new(node));
+        self.items.push(val);
+        self.high_water = ::std::cmp::max(self.high_water, self.items.len());
     }
 
     pub fn pop(&mut self) -> Option<T> {
-        let val = self.top.take();
-        match val {
-            None => None,
-            Some(mut x) => {
-                self.top = x.next.take();
-                Some(x.val)
-            },
+        self.items.pop()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// How many values the stack can hold before it needs to reallocate.
+    pub fn capacity(&self) -> usize {
+        self.items.capacity()
+    }
+
+    /// Remove every value, keeping the allocated capacity.
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// The value on top of the stack, left in place.
+    pub fn peek(&self) -> Option<&T> {
+        self.items.last()
+    }
+
+    /// The most values the stack has ever held at once.
+    /// Popping doesn't lower it, so it records the peak over the stack's whole life.
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water
+    }
+
+    /// Iterate over references to the values, from top to bottom,
+    /// leaving the stack as it is.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter{ inner: self.items.iter().rev() }
+    }
+
+    /// Iterate over the values from top to bottom, along with their positions.
+    /// Positions count from 0 at the top.
+    pub fn iter_indexed(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.iter().enumerate()
+    }
+
+    /// Fold over the values from top to bottom, keeping every intermediate result.
+    /// Gives one result per value, the last being what a fold would give.
+    /// `init` itself isn't included.
+    /// Not called `scan`, which on a stack by value would be `Iterator::scan`.
+    pub fn prefix_scan<B, F>(&self, init: B, f: F) -> Vec<B> where B: Clone, F: Fn(&B, &T) -> B {
+        let mut acc = init;
+        let mut steps = Vec::new();
+        for val in self.iter() {
+            acc = f(&acc, val);
+            steps.push(acc.clone());
+        }
+        steps
+    }
+
+    /// Split the stack into its top half and its bottom half, in that order.
+    /// Each half keeps its values in their original order. When there's
+    /// an odd number of values, the top half gets the extra one.
+    /// Both halves start out with a high water mark of their own length.
+    pub fn split_half(mut self) -> (Stack<T>, Stack<T>) {
+        let bottom_len = self.items.len() / 2;
+        let top = self.items.split_off(bottom_len);
+        (Stack{ high_water: top.len(), items: top },
+         Stack{ high_water: bottom_len, items: self.items })
+    }
+
+    /// Reverse the order of the stack in place, in O(n).
+    pub fn reverse(&mut self) {
+        self.items.reverse();
+    }
+
+    /// Move the bottom value up to the top, leaving the rest in order beneath it.
+    /// Stacks of fewer than two values are left as they are.
+    pub fn bring_bottom_to_top(&mut self) {
+        if self.items.len() < 2 {
+            return;
+        }
+        self.items.rotate_left(1);
+    }
+
+    /// Keep only the values for which `f` returns true, in their existing order.
+    /// The values are compacted in place, so nothing is reallocated.
+    pub fn retain<F>(&mut self, f: F) where F: Fn(&T) -> bool {
+        self.items.retain(f);
+    }
+
+    /// Turn the stack into a queue of the same values, in O(1).
+    /// The bottom of the stack becomes the front of the queue and the top its back,
+    /// so values are dequeued in the order they were originally pushed.
+    pub fn into_queue(self) -> Queue<T> {
+        Queue::from_stack(self)
+    }
+
+    /// Combine two stacks by taking values from their tops in turn, starting
+    /// with this one, and carrying on with whichever has values left once the
+    /// other runs out. The result pops in the order the values were taken,
+    /// so its top is this stack's old top, followed by `other`'s old top.
+    pub fn interleave(mut self, mut other: Stack<T>) -> Stack<T> {
+        let mut woven = Stack::with_capacity(self.len() + other.len());
+        loop {
+            match (self.pop(), other.pop()) {
+                (None, None) => break,
+                (a, b) => {
+                    for v in a.into_iter().chain(b) {
+                        woven.push(v);
+                    }
+                },
+            }
+        }
+
+        // Taken top first, so flip it to put the first taken back on top.
+        woven.reverse();
+        woven
+    }
+
+    /// Pop values off the top for as long as they satisfy `pred`.
+    /// Returns them in the order they were popped, and leaves the first
+    /// value which doesn't match on top of the stack.
+    pub fn pop_while<F>(&mut self, pred: F) -> Vec<T> where F: Fn(&T) -> bool {
+        let mut popped = Vec::new();
+        while self.peek().is_some_and(&pred) {
+            popped.push(self.pop().unwrap());
         }
+        popped
+    }
+}
+
+impl<T> Stack<T> where T: PartialEq {
+    /// Run-length encode the stack, collapsing each run of equal neighbouring values
+    /// into a `(value, count)` pair. Runs are scanned from the top down, and the
+    /// pairs keep the same order as their runs, so the top run ends up on top.
+    pub fn rle(mut self) -> Stack<(T, usize)> {
+        // Popping builds the pairs upside down...
+        let mut runs: Stack<(T, usize)> = Stack::new();
+        while let Some(val) = self.pop() {
+            match runs.items.last_mut() {
+                Some(run) if run.0 == val => run.1 += 1,
+                _ => runs.push((val, 1)),
+            }
+        }
+
+        // ...so flip them back over.
+        runs.reverse();
+        runs
+    }
+}
+
+impl<T> Stack<T> where T: Ord {
+    /// Check whether the values strictly increase from the bottom of the stack to the top.
+    /// Empty and single-value stacks count as monotonic.
+    pub fn is_monotonic_increasing(&self) -> bool {
+        self.iter().zip(self.iter().skip(1)).all(|(above, below)| below < above)
+    }
+
+    /// Check whether the values strictly decrease from the bottom of the stack to the top.
+    /// Empty and single-value stacks count as monotonic.
+    pub fn is_monotonic_decreasing(&self) -> bool {
+        self.iter().zip(self.iter().skip(1)).all(|(above, below)| below > above)
+    }
+}
+
+impl<T> Stack<T> where T: Ord + Clone {
+    /// Drain the stack into a height-balanced `BinaryTree`.
+    /// The tree holds a set, so duplicate values are only kept once.
+    pub fn into_sorted_tree(self) -> BinaryTree<T> {
+        let mut values: Vec<T> = self.collect();
+        values.sort();
+        values.dedup();
+        BinaryTree::from_sorted_slice(&values)
+    }
+}
+
+/// Merge two stacks which each pop in ascending order into one which
+/// pops all of their values in ascending order, in O(n + m).
+pub fn merge_sorted_stacks<T>(mut a: Stack<T>, mut b: Stack<T>) -> Stack<T> where T: Ord {
+    // Repeatedly move across the smaller top, which leaves the largest value on top.
+    let mut merged = Stack::with_capacity(a.len() + b.len());
+    loop {
+        let from_a = match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) => x <= y,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => break,
+        };
+        let val = if from_a { a.pop() } else { b.pop() };
+        merged.push(val.unwrap());
+    }
+
+    // Then flip it over so the smallest is back on top.
+    merged.reverse();
+    merged
+}
+
+/// Iterator over references to the values of a stack, from top to bottom.
+pub struct Iter<'a, T: 'a> {
+    inner: Rev<slice::Iter<'a, T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
     }
 }
 
@@ -81,6 +281,31 @@ impl<T> Iterator for Stack<T> {
     }
 }
 
+/// Stacks are equal when they hold equal values in the same order.
+/// How high they've ever been doesn't count.
+impl<T> PartialEq for Stack<T> where T: PartialEq {
+    fn eq(&self, other: &Stack<T>) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T> Eq for Stack<T> where T: Eq {}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Stack<T> {
+        Stack::new()
+    }
+}
+
+/// Collect by pushing each item in turn, so the last item ends up on top.
+/// Iterating over the stack then gives the items back in reverse order.
+impl<T> ::std::iter::FromIterator<T> for Stack<T> {
+    fn from_iter<I>(iter: I) -> Stack<T> where I: IntoIterator<Item = T> {
+        let items: Vec<T> = iter.into_iter().collect();
+        Stack{ high_water: items.len(), items }
+    }
+}
+
 #[test]
 fn push_pop_ptrs() {
     #[derive(PartialEq,Eq,Debug)]
@@ -101,3 +326,459 @@ fn push_pop_ptrs() {
     assert_eq!(s.pop(), Some(&a));
     assert_eq!(s.pop(), None);
 }
+
+#[test]
+fn len() {
+    let mut s = Stack::<i32>::new();
+    assert_eq!(s.len(), 0);
+    assert!(s.is_empty());
+
+    for x in 0..5 {
+        s.push(x);
+    }
+    assert_eq!(s.len(), 5);
+    assert!(!s.is_empty());
+
+    s.pop();
+    s.pop();
+    s.push(9);
+    assert_eq!(s.len(), 4);
+
+    while s.pop().is_some() {}
+    assert_eq!(s.len(), 0);
+    assert!(s.is_empty());
+
+    // Popping an empty stack leaves it at zero.
+    assert_eq!(s.pop(), None);
+    assert_eq!(s.len(), 0);
+}
+
+#[test]
+fn clear() {
+    let mut s = Stack::<i32>::new();
+    for x in 0..100_000 {
+        s.push(x);
+    }
+    s.clear();
+    assert!(s.is_empty());
+    assert_eq!(s.pop(), None);
+
+    s.push(1);
+    assert_eq!(s.len(), 1);
+    assert_eq!(s.pop(), Some(1));
+}
+
+#[test]
+fn drop_large() {
+    // On a thread with a small stack, to check nothing is dropped recursively.
+    let dropper = ::std::thread::Builder::new().stack_size(64 * 1024).spawn(|| {
+        let mut s = Stack::new();
+        for x in 0..1_000_000 {
+            s.push(x);
+        }
+        drop(s);
+    }).unwrap();
+    assert!(dropper.join().is_ok());
+}
+
+#[test]
+fn with_capacity() {
+    let mut s = Stack::<u64>::with_capacity(1_000_000);
+    assert!(s.is_empty());
+    let reserved = s.capacity();
+    assert!(reserved >= 1_000_000);
+
+    // Filling it up to the reserved size doesn't reallocate.
+    for x in 0..1_000_000 {
+        s.push(x);
+    }
+    assert_eq!(s.capacity(), reserved);
+    assert_eq!(s.len(), 1_000_000);
+    assert_eq!(s.peek(), Some(&999_999));
+
+    let mut expected = 1_000_000;
+    while let Some(x) = s.pop() {
+        expected -= 1;
+        assert_eq!(x, expected);
+    }
+    assert_eq!(expected, 0);
+    assert_eq!(s.high_water_mark(), 1_000_000);
+}
+
+#[test]
+fn peek() {
+    let mut s = Stack::<i32>::new();
+    assert_eq!(s.peek(), None);
+
+    s.push(1);
+    s.push(2);
+    assert_eq!(s.peek(), Some(&2));
+    assert_eq!(s.peek(), Some(&2));
+
+    assert_eq!(s.pop(), Some(2));
+    assert_eq!(s.peek(), Some(&1));
+    assert_eq!(s.pop(), Some(1));
+    assert_eq!(s.peek(), None);
+}
+
+#[test]
+fn from_iter() {
+    let mut s: Stack<i32> = vec![1, 2, 3].into_iter().collect();
+    assert_eq!(s.len(), 3);
+    assert_eq!(s.pop(), Some(3));
+    assert_eq!(s.pop(), Some(2));
+    assert_eq!(s.pop(), Some(1));
+    assert_eq!(s.pop(), None);
+
+    // Collecting a stack back up reverses it.
+    let s: Stack<i32> = (1..=3).collect();
+    let flipped: Stack<i32> = s.collect();
+    assert_eq!(flipped.collect::<Vec<_>>(), vec![1, 2, 3]);
+}
+
+#[test]
+fn default() {
+    let mut s = Stack::<i32>::default();
+    assert!(s.is_empty());
+    assert_eq!(s.pop(), None);
+
+    // Which lets the values be taken out, leaving an empty stack behind.
+    s.push(1);
+    let taken = ::std::mem::take(&mut s);
+    assert!(s.is_empty());
+    assert_eq!(taken.len(), 1);
+}
+
+#[test]
+fn eq() {
+    let mut a = Stack::<i32>::new();
+    let mut b = Stack::<i32>::new();
+    assert_eq!(a, b);
+
+    for x in 1..4 {
+        a.push(x);
+        b.push(x);
+    }
+    assert_eq!(a, b);
+
+    // Same values, different order.
+    let c: Stack<i32> = vec![3, 2, 1].into_iter().collect();
+    assert_ne!(a, c);
+
+    // A stack isn't equal to part of itself.
+    b.pop();
+    assert_ne!(a, b);
+    a.pop();
+    assert_eq!(a, b);
+    assert_ne!(a, Stack::new());
+}
+
+#[test]
+fn pop_while() {
+    let mut s = Stack::<i32>::new();
+    assert_eq!(s.pop_while(|_| true), Vec::<i32>::new());
+
+    for x in &[7, 1, 2, 3] {
+        s.push(*x);
+    }
+
+    assert_eq!(s.pop_while(|x| *x < 5), vec![3, 2, 1]);
+    assert_eq!(s.pop_while(|x| *x < 5), Vec::<i32>::new());
+
+    // The value that stopped the run is still there.
+    assert_eq!(s.pop(), Some(7));
+    assert_eq!(s.pop(), None);
+}
+
+#[test]
+fn split_half_even() {
+    let mut s = Stack::<i32>::new();
+    for x in 1..7 {
+        s.push(x);
+    }
+    s.pop();
+    s.pop();
+
+    // The peak from before the split isn't carried over to either half.
+    let (top, bottom) = s.split_half();
+    assert_eq!(top.high_water_mark(), 2);
+    assert_eq!(bottom.high_water_mark(), 2);
+    assert_eq!(top.collect::<Vec<_>>(), vec![4, 3]);
+    assert_eq!(bottom.collect::<Vec<_>>(), vec![2, 1]);
+}
+
+#[test]
+fn split_half_odd() {
+    let mut s = Stack::<i32>::new();
+    for x in 1..6 {
+        s.push(x);
+    }
+
+    let (top, bottom) = s.split_half();
+    assert_eq!(top.high_water_mark(), 3);
+    assert_eq!(bottom.high_water_mark(), 2);
+    assert_eq!(top.collect::<Vec<_>>(), vec![5, 4, 3]);
+    assert_eq!(bottom.collect::<Vec<_>>(), vec![2, 1]);
+
+    let mut one = Stack::<i32>::new();
+    one.push(1);
+    let (top, bottom) = one.split_half();
+    assert_eq!(top.collect::<Vec<_>>(), vec![1]);
+    assert_eq!(bottom.collect::<Vec<_>>(), Vec::<i32>::new());
+
+    let (top, bottom) = Stack::<i32>::new().split_half();
+    assert_eq!(top.count() + bottom.count(), 0);
+}
+
+#[test]
+fn into_sorted_tree() {
+    let mut s = Stack::<i32>::new();
+    for x in &[9, 3, 14, 1, 7, 12, 5, 0, 11, 2, 13, 8, 4, 10, 6, 3, 9] {
+        s.push(*x);
+    }
+
+    let t = s.into_sorted_tree();
+    assert_eq!(t.iter().cloned().collect::<Vec<_>>(), (0..15).collect::<Vec<_>>());
+    // 15 values fit exactly in a perfect tree of height 3.
+    assert_eq!(t.height(), 3);
+
+    assert_eq!(Stack::<i32>::new().into_sorted_tree().iter().count(), 0);
+}
+
+#[test]
+fn merge_sorted() {
+    let mut a = Stack::<i32>::new();
+    let mut b = Stack::<i32>::new();
+    for x in &[9, 6, 4, 1] {
+        a.push(*x);
+    }
+    for x in &[10, 8, 6, 3, 2] {
+        b.push(*x);
+    }
+
+    let merged = merge_sorted_stacks(a, b);
+    assert_eq!(merged.collect::<Vec<_>>(), vec![1, 2, 3, 4, 6, 6, 8, 9, 10]);
+
+    let mut c = Stack::<i32>::new();
+    c.push(2);
+    c.push(1);
+    let merged = merge_sorted_stacks(c, Stack::new());
+    assert_eq!(merged.collect::<Vec<_>>(), vec![1, 2]);
+}
+
+#[test]
+fn iter() {
+    let mut s = Stack::<i32>::new();
+    assert_eq!(s.iter().count(), 0);
+
+    for x in 1..=4 {
+        s.push(x);
+    }
+    let first: Vec<_> = s.iter().cloned().collect();
+    let second: Vec<_> = s.iter().cloned().collect();
+    assert_eq!(first, vec![4, 3, 2, 1]);
+    assert_eq!(first, second);
+    assert_eq!(s.len(), 4);
+    assert_eq!(s.pop(), Some(4));
+}
+
+#[test]
+fn iter_indexed() {
+    let mut s = Stack::<&'static str>::new();
+    assert_eq!(s.iter_indexed().count(), 0);
+
+    s.push("c");
+    s.push("b");
+    s.push("a");
+
+    let pairs: Vec<_> = s.iter_indexed().map(|(i, v)| (i, *v)).collect();
+    assert_eq!(pairs, vec![(0, "a"), (1, "b"), (2, "c")]);
+
+    // Nothing was consumed.
+    assert_eq!(s.pop(), Some("a"));
+}
+
+#[test]
+fn retain() {
+    let mut s = Stack::<i32>::new();
+    s.retain(|_| false);
+    assert_eq!(s.pop(), None);
+
+    for x in 1..9 {
+        s.push(x);
+    }
+
+    s.retain(|x| x % 2 == 0);
+    assert_eq!(s.iter().count(), 4);
+    assert_eq!(s.collect::<Vec<_>>(), vec![8, 6, 4, 2]);
+}
+
+#[test]
+fn monotonic() {
+    let mut s = Stack::<i32>::new();
+    assert!(s.is_monotonic_increasing());
+    assert!(s.is_monotonic_decreasing());
+
+    s.push(1);
+    assert!(s.is_monotonic_increasing());
+    assert!(s.is_monotonic_decreasing());
+
+    for x in &[3, 4, 9] {
+        s.push(*x);
+    }
+    assert!(s.is_monotonic_increasing());
+    assert!(!s.is_monotonic_decreasing());
+
+    let mut d = Stack::<i32>::new();
+    for x in &[9, 4, 3, 1] {
+        d.push(*x);
+    }
+    assert!(!d.is_monotonic_increasing());
+    assert!(d.is_monotonic_decreasing());
+
+    // Neither, and repeats break strictness either way.
+    let mut n = Stack::<i32>::new();
+    for x in &[1, 5, 2] {
+        n.push(*x);
+    }
+    assert!(!n.is_monotonic_increasing());
+    assert!(!n.is_monotonic_decreasing());
+
+    let mut r = Stack::<i32>::new();
+    r.push(2);
+    r.push(2);
+    assert!(!r.is_monotonic_increasing());
+    assert!(!r.is_monotonic_decreasing());
+}
+
+#[test]
+fn reverse() {
+    let mut s = Stack::<i32>::new();
+    s.reverse();
+    assert_eq!(s.pop(), None);
+
+    s.push(1);
+    s.reverse();
+    assert_eq!(s.iter().collect::<Vec<_>>(), vec![&1]);
+
+    for x in 2..100001 {
+        s.push(x);
+    }
+    s.reverse();
+    assert!(s.collect::<Vec<_>>().into_iter().eq(1..100001));
+}
+
+#[test]
+fn rle() {
+    let mut s = Stack::<char>::new();
+    for c in "aaabccddddb".chars() {
+        s.push(c);
+    }
+
+    let runs = s.rle();
+    assert_eq!(runs.collect::<Vec<_>>(),
+               vec![('b', 1), ('d', 4), ('c', 2), ('b', 1), ('a', 3)]);
+
+    assert_eq!(Stack::<char>::new().rle().count(), 0);
+}
+
+#[test]
+fn into_queue() {
+    let mut s = Stack::<i32>::new();
+    for x in 1..6 {
+        s.push(x);
+    }
+
+    let mut q = s.into_queue();
+    assert_eq!(q.dequeue(), Some(1));
+    q.enqueue(6);
+    assert_eq!(q.collect::<Vec<_>>(), vec![2, 3, 4, 5, 6]);
+
+    assert_eq!(Stack::<i32>::new().into_queue().dequeue(), None);
+}
+
+#[test]
+fn high_water_mark() {
+    let mut s = Stack::<i32>::new();
+    assert_eq!(s.high_water_mark(), 0);
+
+    for x in 0..10 {
+        s.push(x);
+    }
+    for _ in 0..7 {
+        s.pop();
+    }
+    assert_eq!(s.high_water_mark(), 10);
+
+    // Climbing back up without passing the peak leaves it alone.
+    for x in 0..5 {
+        s.push(x);
+    }
+    assert_eq!(s.high_water_mark(), 10);
+    s.push(5);
+    s.push(6);
+    s.push(7);
+    assert_eq!(s.high_water_mark(), 11);
+
+    while s.pop().is_some() {}
+    assert_eq!(s.high_water_mark(), 11);
+}
+
+#[test]
+fn interleave() {
+    let mut a = Stack::<i32>::new();
+    let mut b = Stack::<i32>::new();
+    for x in &[5, 3, 1] {
+        a.push(*x);
+    }
+    for x in &[6, 4, 2] {
+        b.push(*x);
+    }
+    assert_eq!(a.interleave(b).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 6]);
+
+    let mut long = Stack::<i32>::new();
+    let mut short = Stack::<i32>::new();
+    for x in &[9, 8, 7, 5, 3, 1] {
+        long.push(*x);
+    }
+    short.push(2);
+    assert_eq!(short.interleave(long).collect::<Vec<_>>(), vec![2, 1, 3, 5, 7, 8, 9]);
+
+    assert_eq!(Stack::<i32>::new().interleave(Stack::new()).count(), 0);
+}
+
+#[test]
+fn prefix_scan() {
+    assert_eq!(Stack::<i32>::new().prefix_scan(0, |acc, x| acc + x), Vec::<i32>::new());
+
+    let mut s = Stack::<i32>::new();
+
+    for x in &[4, 3, 2, 1] {
+        s.push(*x);
+    }
+    assert_eq!(s.prefix_scan(0, |acc, x| acc + x), vec![1, 3, 6, 10]);
+    assert_eq!(s.prefix_scan(String::new(), |acc, x| format!("{}{}", acc, x)),
+               vec!["1", "12", "123", "1234"]);
+
+    // Nothing was consumed.
+    assert_eq!(s.pop(), Some(1));
+}
+
+#[test]
+fn bring_bottom_to_top() {
+    let mut s = Stack::<i32>::new();
+    s.bring_bottom_to_top();
+    assert_eq!(s.pop(), None);
+
+    s.push(1);
+    s.bring_bottom_to_top();
+    assert_eq!(s.iter().collect::<Vec<_>>(), vec![&1]);
+
+    for x in 2..6 {
+        s.push(x);
+    }
+    s.bring_bottom_to_top();
+    assert_eq!(s.iter().cloned().collect::<Vec<_>>(), vec![1, 5, 4, 3, 2]);
+    s.bring_bottom_to_top();
+    assert_eq!(s.collect::<Vec<_>>(), vec![2, 1, 5, 4, 3]);
+}